@@ -0,0 +1,467 @@
+use crate::blk::{
+	blk_string::{BlkString, blk_str},
+	blk_structure::BlkField,
+	blk_type::BlkType,
+	error::GetError,
+};
+
+/// Rust types a [`BlkType`] can be read as without conversion
+pub trait FromBlkType: Sized {
+	/// Type name as written in BLK text, used in errors
+	const BLK_TYPE: &'static str;
+
+	fn from_blk_type(value: &BlkType) -> Option<Self>;
+}
+
+macro_rules! from_blk_type {
+	($($ty:ty => $name:literal, $variant:ident($v:ident) => $conv:expr;)*) => {
+		$(
+			impl FromBlkType for $ty {
+				const BLK_TYPE: &'static str = $name;
+
+				fn from_blk_type(value: &BlkType) -> Option<Self> {
+					match value {
+						BlkType::$variant($v) => Some($conv),
+						_ => None,
+					}
+				}
+			}
+		)*
+	};
+}
+
+from_blk_type! {
+	BlkString => "t", Str(v) => v.clone();
+	String => "t", Str(v) => v.to_string();
+	i32 => "i", Int(v) => *v;
+	[i32; 2] => "ip2", Int2(v) => *v;
+	[i32; 3] => "ip3", Int3(v) => *v;
+	[i32; 4] => "ip4", Int4(v) => **v;
+	i64 => "i64", Long(v) => *v;
+	f32 => "r", Float(v) => *v;
+	[f32; 2] => "p2", Float2(v) => *v;
+	[f32; 3] => "p3", Float3(v) => *v;
+	[f32; 4] => "p4", Float4(v) => **v;
+	[f32; 12] => "m", Float12(v) => **v;
+	bool => "b", Bool(v) => *v;
+}
+
+impl FromBlkType for BlkType {
+	const BLK_TYPE: &'static str = "any";
+
+	fn from_blk_type(value: &BlkType) -> Option<Self> {
+		Some(value.clone())
+	}
+}
+
+macro_rules! typed_getter {
+	($($name:ident -> $ty:ty;)*) => {
+		$(
+			#[doc = concat!("Shorthand for [`BlkField::get`] with `", stringify!($ty), "`")]
+			pub fn $name(&self, path: &str) -> Result<$ty, GetError> {
+				self.get(path)
+			}
+		)*
+	};
+}
+
+impl BlkField {
+	typed_getter! {
+		get_i32 -> i32;
+		get_i64 -> i64;
+		get_f32 -> f32;
+		get_bool -> bool;
+		get_float2 -> [f32; 2];
+		get_float3 -> [f32; 3];
+		get_float4 -> [f32; 4];
+		get_matrix -> [f32; 12];
+	}
+
+	/// Field at a `/` separated path, an empty path yields the field itself
+	///
+	/// Unlike [`BlkField::pointer`], this borrows and works for structs as well.
+	/// With duplicate keys, the first field of that name is used unless a 0-based index is given, as in `weapon[2]/bullet`
+	pub fn get_field(&self, path: &str) -> Result<&BlkField, GetError> {
+		let mut current = self;
+		// Path walked so far, for errors
+		let mut walked: Vec<&str> = vec![];
+		for segment in segments(path) {
+			if let BlkField::Value(..) = current {
+				return Err(GetError::NotAStruct {
+					path:  walked.join("/"),
+					field: segment.to_owned(),
+				});
+			}
+			walked.push(segment);
+			current = child_position(current.fields(), segment)
+				.map(|i| &current.fields()[i])
+				.ok_or_else(|| GetError::Missing {
+					path: walked.join("/"),
+				})?;
+		}
+		Ok(current)
+	}
+
+	/// Mutable field at a `/` separated path, using the same selectors as [`BlkField::get_field`]
+	pub fn pointer_mut(&mut self, path: &str) -> Option<&mut BlkField> {
+		let mut current = self;
+		for segment in segments(path) {
+			let fields = current.fields_mut()?;
+			let i = child_position(fields, segment)?;
+			current = &mut fields[i];
+		}
+		Some(current)
+	}
+
+	/// Sets the value at a `/` separated path, creating missing blocks along the way
+	///
+	/// An existing value keeps its position, otherwise the value is appended to its block
+	pub fn set(&mut self, path: &str, value: BlkType) -> Result<(), GetError> {
+		let (parent, name) = split_last(path);
+		let block = self.block_mut(parent, name)?;
+		match child_position(block.fields(), name).map(|i| &mut block.fields_mut().unwrap()[i]) {
+			Some(BlkField::Value(_, existing)) => *existing = value,
+			Some(_) => {
+				return Err(GetError::NotAValue {
+					path: path.to_owned(),
+				});
+			},
+			None => block.append(
+				name,
+				path,
+				BlkField::Value(blk_str(parse_segment(name).0), value),
+			)?,
+		}
+		Ok(())
+	}
+
+	/// Appends `field` to the block at a `/` separated path, creating missing blocks along the way
+	pub fn insert(&mut self, path: &str, field: BlkField) -> Result<(), GetError> {
+		let block = self.block_mut(path, &field.get_name())?;
+		block
+			.fields_mut()
+			.expect("block_mut always yields a struct")
+			.push(field);
+		Ok(())
+	}
+
+	/// Removes the field at a `/` separated path, returning it
+	pub fn remove(&mut self, path: &str) -> Option<BlkField> {
+		let (parent, name) = split_last(path);
+		let fields = self.pointer_mut(parent)?.fields_mut()?;
+		let i = child_position(fields, name)?;
+		Some(fields.remove(i))
+	}
+
+	/// Renames the field at a `/` separated path, keeping its position
+	pub fn rename(&mut self, path: &str, new: &str) -> Result<(), GetError> {
+		self.pointer_mut(path)
+			.ok_or_else(|| GetError::Missing {
+				path: path.to_owned(),
+			})?
+			.set_name(blk_str(new));
+		Ok(())
+	}
+
+	// Struct at `path` that `field` is about to be added to, creating missing structs
+	fn block_mut(&mut self, path: &str, field: &str) -> Result<&mut BlkField, GetError> {
+		let mut current = self;
+		let mut walked: Vec<&str> = vec![];
+		for segment in segments(path) {
+			if let BlkField::Value(..) = current {
+				return Err(GetError::NotAStruct {
+					path:  walked.join("/"),
+					field: segment.to_owned(),
+				});
+			}
+			walked.push(segment);
+			let i = match child_position(current.fields(), segment) {
+				Some(i) => i,
+				None => {
+					let (name, _) = parse_segment(segment);
+					current.append(
+						segment,
+						&walked.join("/"),
+						BlkField::new_struct(blk_str(name)),
+					)?;
+					current.fields().len() - 1
+				},
+			};
+			current = &mut current.fields_mut().expect("checked above")[i];
+		}
+		match current {
+			BlkField::Value(..) => Err(GetError::NotAStruct {
+				path:  walked.join("/"),
+				field: field.to_owned(),
+			}),
+			block => Ok(block),
+		}
+	}
+
+	// Appends a new child for `segment`, which may only index one past the existing fields of that name
+	fn append(&mut self, segment: &str, path: &str, field: BlkField) -> Result<(), GetError> {
+		let (name, index) = parse_segment(segment);
+		let existing = self.children(name).count();
+		let fields = self.fields_mut().ok_or_else(|| GetError::NotAStruct {
+			path:  path.to_owned(),
+			field: segment.to_owned(),
+		})?;
+		if index.is_some_and(|i| i != existing) {
+			return Err(GetError::Missing {
+				path: path.to_owned(),
+			});
+		}
+		fields.push(field);
+		Ok(())
+	}
+
+	/// Value at a `/` separated path
+	pub fn get_value(&self, path: &str) -> Result<&BlkType, GetError> {
+		match self.get_field(path)? {
+			BlkField::Value(_, value) => Ok(value),
+			_ => Err(GetError::NotAValue {
+				path: path.to_owned(),
+			}),
+		}
+	}
+
+	/// Value at a `/` separated path, read as `T`
+	///
+	/// ```
+	/// # use wt_blk::blk::plaintext_deserialize::deserialize_blk;
+	/// let blk = deserialize_blk("engine { power:r=1500 }").unwrap();
+	/// assert_eq!(blk.get::<f32>("engine/power").unwrap(), 1500.0);
+	/// assert!(blk.get::<i32>("engine/power").is_err());
+	/// ```
+	pub fn get<T: FromBlkType>(&self, path: &str) -> Result<T, GetError> {
+		let value = self.get_value(path)?;
+		T::from_blk_type(value).ok_or_else(|| GetError::WrongType {
+			path:     path.to_owned(),
+			expected: T::BLK_TYPE,
+			found:    value.blk_type_name(),
+		})
+	}
+
+	/// Same as [`BlkField::get`], falling back to `default` if the path is missing or has another type
+	pub fn get_or<T: FromBlkType>(&self, path: &str, default: T) -> T {
+		self.get(path).unwrap_or(default)
+	}
+
+	/// String at a `/` separated path, borrowed from the field
+	pub fn get_str(&self, path: &str) -> Result<&str, GetError> {
+		match self.get_value(path)? {
+			BlkType::Str(s) => Ok(s.as_str()),
+			other => Err(GetError::WrongType {
+				path:     path.to_owned(),
+				expected: "t",
+				found:    other.blk_type_name(),
+			}),
+		}
+	}
+
+	/// Direct children of a struct, empty for values
+	pub fn fields(&self) -> &[BlkField] {
+		match self {
+			BlkField::Struct(_, fields) | BlkField::Merged(_, fields) => fields,
+			BlkField::Value(..) => &[],
+		}
+	}
+
+	fn fields_mut(&mut self) -> Option<&mut Vec<BlkField>> {
+		match self {
+			BlkField::Struct(_, fields) | BlkField::Merged(_, fields) => Some(fields),
+			BlkField::Value(..) => None,
+		}
+	}
+
+	/// Direct children called `name`, in order of appearance
+	pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a BlkField> + 'a {
+		self.fields()
+			.iter()
+			.filter(move |e| e.get_name().as_str() == name)
+	}
+
+	/// Values of the direct children called `name`, such as repeated keys of one type
+	pub fn values<'a, T: FromBlkType>(
+		&'a self,
+		name: &'a str,
+	) -> impl Iterator<Item = Result<T, GetError>> + 'a {
+		self.children(name).map(move |field| match field {
+			BlkField::Value(_, value) => {
+				T::from_blk_type(value).ok_or_else(|| GetError::WrongType {
+					path:     name.to_owned(),
+					expected: T::BLK_TYPE,
+					found:    value.blk_type_name(),
+				})
+			},
+			_ => Err(GetError::NotAValue {
+				path: name.to_owned(),
+			}),
+		})
+	}
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+	path.split('/').filter(|e| !e.is_empty())
+}
+
+// Splits off the last segment of a path
+fn split_last(path: &str) -> (&str, &str) {
+	let path = path.trim_end_matches('/');
+	path.rsplit_once('/').unwrap_or(("", path))
+}
+
+// Name and index of a selector such as `weapon[2]`
+fn parse_segment(segment: &str) -> (&str, Option<usize>) {
+	segment
+		.strip_suffix(']')
+		.and_then(|e| e.rsplit_once('['))
+		.and_then(|(name, index)| Some((name, Some(index.parse().ok()?))))
+		.unwrap_or((segment, None))
+}
+
+// Position of the child a segment selects
+fn child_position(fields: &[BlkField], segment: &str) -> Option<usize> {
+	let (name, index) = parse_segment(segment);
+	fields
+		.iter()
+		.enumerate()
+		.filter(|(_, e)| e.get_name().as_str() == name)
+		.nth(index.unwrap_or(0))
+		.map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod test {
+	use crate::blk::{
+		blk_string::blk_str,
+		blk_structure::BlkField,
+		blk_type::BlkType,
+		error::GetError,
+		make_strict_test,
+		plaintext_deserialize::deserialize_blk,
+	};
+
+	#[test]
+	fn typed() {
+		let blk = make_strict_test();
+		assert_eq!(blk.get::<i32>("int").unwrap(), 42);
+		assert_eq!(blk.get_str("alpha/str").unwrap(), "hello");
+		assert_eq!(blk.get::<String>("alpha/str").unwrap(), "hello");
+		assert_eq!(blk.get_value("int").unwrap(), &BlkType::Int(42));
+		assert_eq!(blk.get_or("alpha/noexist", 1.5_f32), 1.5);
+		assert_eq!(blk.get_or("int", 1.5_f32), 1.5);
+		assert_eq!(blk.get_or("int", 0), 42);
+	}
+
+	#[test]
+	fn errors() {
+		let blk = make_strict_test();
+		assert_eq!(
+			blk.get_f32("int"),
+			Err(GetError::WrongType {
+				path:     "int".to_owned(),
+				expected: "r",
+				found:    "i",
+			})
+		);
+		assert_eq!(
+			blk.get_bool("alpha/noexist"),
+			Err(GetError::Missing {
+				path: "alpha/noexist".to_owned(),
+			})
+		);
+		assert_eq!(
+			blk.get_str("alpha/str/deeper"),
+			Err(GetError::NotAStruct {
+				path:  "alpha/str".to_owned(),
+				field: "deeper".to_owned(),
+			})
+		);
+		assert_eq!(
+			blk.get_float3("alpha"),
+			Err(GetError::NotAValue {
+				path: "alpha".to_owned(),
+			})
+		);
+	}
+
+	#[test]
+	fn repeated() {
+		let blk = deserialize_blk(
+			"weapon { name:t=\"a\" }\nweapon { name:t=\"b\" }\nspeed:r=1\nspeed:r=2\nspeed:i=3",
+		)
+		.unwrap();
+		let names: Vec<_> = blk
+			.children("weapon")
+			.map(|e| e.get_str("name").unwrap())
+			.collect();
+		assert_eq!(names, ["a", "b"]);
+		let speeds: Vec<_> = blk.values::<f32>("speed").collect();
+		assert_eq!(speeds[..2], [Ok(1.0), Ok(2.0)]);
+		assert!(speeds[2].is_err());
+		assert_eq!(blk.children("missing").count(), 0);
+	}
+
+	#[test]
+	fn indexed() {
+		let blk = deserialize_blk(
+			"weapon { bullet { mass:r=1 } }\nweapon { bullet { mass:r=2 } }\nweapon { bullet { mass:r=3 } }",
+		)
+		.unwrap();
+		assert_eq!(blk.get_f32("weapon/bullet/mass").unwrap(), 1.0);
+		assert_eq!(blk.get_f32("weapon[2]/bullet/mass").unwrap(), 3.0);
+		assert!(blk.get_field("weapon[3]").is_err());
+	}
+
+	#[test]
+	fn mutate() {
+		let mut blk =
+			deserialize_blk("weapon { mass:r=1 }\nweapon { mass:r=2 }\nspeed:r=1").unwrap();
+		blk.set("weapon[1]/mass", BlkType::Float(5.0)).unwrap();
+		blk.set("engine/turbo/enabled", BlkType::Bool(true))
+			.unwrap();
+		blk.set("weapon[2]/mass", BlkType::Float(3.0)).unwrap();
+		blk.insert(
+			"weapon[0]",
+			BlkField::Value(blk_str("caliber"), BlkType::Float(12.7)),
+		)
+		.unwrap();
+		blk.rename("speed", "max_speed").unwrap();
+		assert_eq!(
+			blk.remove("weapon[1]/mass"),
+			Some(BlkField::Value(blk_str("mass"), BlkType::Float(5.0)))
+		);
+		*blk.pointer_mut("max_speed").unwrap() =
+			BlkField::Value(blk_str("max_speed"), BlkType::Int(2));
+		assert_eq!(
+			blk,
+			deserialize_blk(
+				"weapon { mass:r=1; caliber:r=12.7 }\nweapon {}\nmax_speed:i=2\nengine { turbo { enabled:b=yes } }\nweapon { mass:r=3 }"
+			)
+			.unwrap()
+		);
+
+		assert!(blk.set("weapon[5]/mass", BlkType::Int(1)).is_err());
+		assert!(blk.set("max_speed[2]", BlkType::Int(1)).is_err());
+		assert_eq!(
+			blk.set("max_speed/x", BlkType::Int(1)),
+			Err(GetError::NotAStruct {
+				path:  "max_speed".to_owned(),
+				field: "x".to_owned(),
+			})
+		);
+		assert!(blk.set("engine", BlkType::Int(1)).is_err());
+		assert!(blk.rename("missing", "x").is_err());
+		assert_eq!(blk.remove("missing"), None);
+	}
+
+	#[test]
+	fn indexed_leaf() {
+		let mut blk = deserialize_blk("speed:r=1").unwrap();
+		blk.set("speed[1]", BlkType::Float(2.0)).unwrap();
+		blk.set("speed[0]", BlkType::Float(3.0)).unwrap();
+		assert_eq!(blk, deserialize_blk("speed:r=3\nspeed:r=2").unwrap());
+		assert_eq!(blk.get::<f32>("speed[1]"), Ok(2.0));
+	}
+}
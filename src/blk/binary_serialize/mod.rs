@@ -0,0 +1,2 @@
+/// Exports core functions for packing BLK files
+pub mod writer;
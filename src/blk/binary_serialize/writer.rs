@@ -0,0 +1,404 @@
+use std::sync::Arc;
+
+use foldhash::HashMapExt;
+use indexmap::{IndexMap, IndexSet};
+
+use crate::blk::{
+	blk_string::BlkString,
+	blk_structure::BlkField,
+	blk_type::BlkType,
+	error::WriteError,
+	file::FileType,
+	leb128::write_uleb128,
+	name_map::NameMap,
+};
+
+/// Name IDs in the params info are stored as 24-bit integers
+const MAX_NAME_ID: usize = 0xFF_FFFF;
+
+/// Strings stored in the name map are tagged with the highest bit set
+const NM_TAG: u32 = 1 << 31;
+
+/// Inverse of [`crate::blk::blk_block_hierarchy::FlatBlock`], a struct whose fields are split into
+/// plain values and a range of sub-blocks
+struct FlatWriteBlock<'a> {
+	// None for the root block
+	name:   Option<&'a BlkString>,
+	params: Vec<(&'a BlkString, &'a BlkType)>,
+	blocks: usize,
+	offset: usize,
+}
+
+/// Decides where keys and string values are stored, which is what differentiates FAT from SLIM
+pub(crate) trait NameSink {
+	/// Index of the name in the name map that is referenced by keys and block names
+	fn name_id(&mut self, name: &BlkString) -> Result<usize, WriteError>;
+
+	/// Returns the index in the name map if the string should be referenced from there,
+	/// otherwise the string is written to the params data
+	fn string_id(&mut self, value: &BlkString) -> Result<Option<usize>, WriteError>;
+}
+
+/// FAT files carry their own name map, with string values stored inline in the params data
+#[derive(Default)]
+struct FatNames {
+	names: IndexSet<BlkString>,
+}
+
+impl FatNames {
+	/// Registers names in order of appearance, which is how the game orders them
+	fn collect(&mut self, fields: &[BlkField]) {
+		for field in fields {
+			self.names.insert(field.get_name());
+			if let BlkField::Struct(_, children) = field {
+				self.collect(children);
+			}
+		}
+	}
+}
+
+impl NameSink for FatNames {
+	fn name_id(&mut self, name: &BlkString) -> Result<usize, WriteError> {
+		Ok(self.names.insert_full(name.clone()).0)
+	}
+
+	fn string_id(&mut self, _: &BlkString) -> Result<Option<usize>, WriteError> {
+		Ok(None)
+	}
+}
+
+/// Resolves keys and string values against a shared [`NameMap`], as SLIM files store no names themselves
+pub struct SlimNameTable {
+	base:        Arc<NameMap>,
+	lookup:      foldhash::HashMap<BlkString, usize>,
+	appended:    Vec<BlkString>,
+	collect_new: bool,
+}
+
+impl SlimNameTable {
+	/// Fails with [`WriteError::MissingName`] when a name is not already part of the name map
+	pub fn strict(nm: Arc<NameMap>) -> Self {
+		Self::new(nm, false)
+	}
+
+	/// Appends names missing from the name map, retrieve the updated map with [`Self::into_name_map`]
+	pub fn collecting(nm: Arc<NameMap>) -> Self {
+		Self::new(nm, true)
+	}
+
+	fn new(base: Arc<NameMap>, collect_new: bool) -> Self {
+		let mut lookup = foldhash::HashMap::with_capacity(base.parsed.len());
+		for (i, name) in base.parsed.iter().enumerate() {
+			// Duplicates resolve to their first occurrence
+			lookup.entry(name.clone()).or_insert(i);
+		}
+		Self {
+			base,
+			lookup,
+			appended: vec![],
+			collect_new,
+		}
+	}
+
+	/// Names that were not part of the original name map, in the order they were appended
+	pub fn new_names(&self) -> &[BlkString] {
+		&self.appended
+	}
+
+	/// Yields the original name map with all collected names appended
+	/// Files written against the original map remain valid, as existing indices do not change
+	pub fn into_name_map(self) -> Arc<NameMap> {
+		if self.appended.is_empty() {
+			return self.base;
+		}
+		let mut names = self.base.parsed.as_ref().clone();
+		names.extend(self.appended);
+		Arc::new(NameMap::from_names(names))
+	}
+}
+
+impl NameSink for SlimNameTable {
+	fn name_id(&mut self, name: &BlkString) -> Result<usize, WriteError> {
+		if let Some(idx) = self.lookup.get(name) {
+			return Ok(*idx);
+		}
+		if !self.collect_new {
+			return Err(WriteError::MissingName(name.to_string()));
+		}
+		let idx = self.base.parsed.len() + self.appended.len();
+		self.appended.push(name.clone());
+		self.lookup.insert(name.clone(), idx);
+		Ok(idx)
+	}
+
+	fn string_id(&mut self, value: &BlkString) -> Result<Option<usize>, WriteError> {
+		self.name_id(value).map(Some)
+	}
+}
+
+impl BlkField {
+	/// Serializes into a FAT BLK file, including its leading [`FileType`] byte
+	pub fn to_fat_blk(&self) -> Result<Vec<u8>, WriteError> {
+		let mut names = FatNames::default();
+		names.collect(self.root_fields()?);
+
+		// The body has to be written first, as it finalizes the name map
+		let mut body = vec![];
+		self.write_blk_body(&mut names, &mut body)?;
+
+		let mut names_data = vec![];
+		for name in &names.names {
+			names_data.extend_from_slice(name.as_bytes());
+			names_data.push(0);
+		}
+
+		let mut out = Vec::with_capacity(names_data.len() + body.len() + 8);
+		out.push(FileType::FAT as u8);
+		write_uleb128(&mut out, names.names.len());
+		write_uleb128(&mut out, names_data.len());
+		out.extend_from_slice(&names_data);
+		out.extend_from_slice(&body);
+		Ok(out)
+	}
+
+	/// Serializes into a SLIM BLK file, including its leading [`FileType`] byte
+	/// All keys and strings have to be present in the name map, see [`SlimNameTable::collecting`] otherwise
+	pub fn to_slim_blk(&self, nm: Arc<NameMap>) -> Result<Vec<u8>, WriteError> {
+		self.to_slim_blk_with(&mut SlimNameTable::strict(nm))
+	}
+
+	/// Serializes into a SLIM BLK file, resolving names with the provided table
+	pub fn to_slim_blk_with(&self, names: &mut SlimNameTable) -> Result<Vec<u8>, WriteError> {
+		let mut out = vec![FileType::SLIM as u8];
+		// SLIM files have an empty name map, all names are looked up from the shared one
+		write_uleb128(&mut out, 0);
+		self.write_blk_body(names, &mut out)?;
+		Ok(out)
+	}
+
+	fn root_fields(&self) -> Result<&[BlkField], WriteError> {
+		match self {
+			BlkField::Struct(_, fields) => Ok(fields),
+			BlkField::Merged(name, _) => Err(WriteError::MergedField(name.to_string())),
+			BlkField::Value(..) => Err(WriteError::RootNotStruct),
+		}
+	}
+
+	/// Writes everything following the name map: the params and the nesting map
+	pub(crate) fn write_blk_body(
+		&self,
+		names: &mut impl NameSink,
+		out: &mut Vec<u8>,
+	) -> Result<(), WriteError> {
+		let blocks = flatten(self.root_fields()?)?;
+
+		let params_count = blocks.iter().map(|b| b.params.len()).sum();
+		let mut params_info = Vec::with_capacity(params_count * 8);
+
+		// Strings are laid out in front of all other values
+		let mut data = vec![];
+		let mut string_offsets: IndexMap<&str, usize> = IndexMap::new();
+		for (_, value) in blocks.iter().flat_map(|b| b.params.iter()) {
+			if let BlkType::Str(s) = value
+				&& names.string_id(s)?.is_none()
+				&& !string_offsets.contains_key(s.as_str())
+			{
+				string_offsets.insert(s.as_str(), data.len());
+				data.extend_from_slice(s.as_bytes());
+				data.push(0);
+			}
+		}
+		data.resize(data.len().next_multiple_of(4), 0);
+
+		for (name, value) in blocks.iter().flat_map(|b| b.params.iter()) {
+			let name_id = names.name_id(name)?;
+			if name_id > MAX_NAME_ID {
+				return Err(WriteError::SectionOverflow {
+					section: "Name map",
+					count:   name_id + 1,
+					max:     MAX_NAME_ID + 1,
+				});
+			}
+			params_info.extend_from_slice(&(name_id as u32).to_le_bytes()[..3]);
+			params_info.push(value.type_code().into());
+
+			let field: [u8; 4] = match value {
+				BlkType::Str(s) => match names.string_id(s)? {
+					Some(idx) => (idx as u32 | NM_TAG).to_le_bytes(),
+					None => (string_offsets[s.as_str()] as u32).to_le_bytes(),
+				},
+				BlkType::Int(v) => v.to_le_bytes(),
+				BlkType::Float(v) => v.to_le_bytes(),
+				BlkType::Bool(v) => (*v as u32).to_le_bytes(),
+				BlkType::Color { r, g, b, a } => [*r, *g, *b, *a],
+				_ => {
+					let offset = data.len() as u32;
+					write_outlined(value, &mut data);
+					offset.to_le_bytes()
+				},
+			};
+			params_info.extend_from_slice(&field);
+		}
+
+		write_uleb128(out, blocks.len());
+		write_uleb128(out, params_count);
+		write_uleb128(out, data.len());
+		out.extend_from_slice(&data);
+		out.extend_from_slice(&params_info);
+
+		for block in &blocks {
+			let name_id = match block.name {
+				Some(name) => names.name_id(name)? + 1,
+				None => 0,
+			};
+			write_uleb128(out, name_id);
+			write_uleb128(out, block.params.len());
+			write_uleb128(out, block.blocks);
+			if block.blocks > 0 {
+				write_uleb128(out, block.offset);
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Orders blocks breadth-first, such that the sub-blocks of each block are contiguous
+fn flatten(root: &[BlkField]) -> Result<Vec<FlatWriteBlock<'_>>, WriteError> {
+	let mut pending: Vec<(Option<&BlkString>, &[BlkField])> = vec![(None, root)];
+	let mut flat = vec![];
+
+	let mut i = 0;
+	while let Some(&(name, fields)) = pending.get(i) {
+		let offset = pending.len();
+		let mut params = vec![];
+		for field in fields {
+			match field {
+				BlkField::Value(k, v) => params.push((k, v)),
+				BlkField::Struct(k, children) => pending.push((Some(k), children)),
+				BlkField::Merged(k, _) => return Err(WriteError::MergedField(k.to_string())),
+			}
+		}
+		flat.push(FlatWriteBlock {
+			name,
+			params,
+			blocks: pending.len() - offset,
+			offset,
+		});
+		i += 1;
+	}
+	Ok(flat)
+}
+
+/// Appends the payload of a non-inline value to the params data
+fn write_outlined(value: &BlkType, data: &mut Vec<u8>) {
+	let mut floats = |v: &[f32]| v.iter().for_each(|f| data.extend(f.to_le_bytes()));
+	match value {
+		BlkType::Float2(v) => floats(v),
+		BlkType::Float3(v) => floats(v),
+		BlkType::Float4(v) => floats(v.as_ref()),
+		BlkType::Float12(v) => floats(v.as_ref()),
+		BlkType::Int2(v) => v.iter().for_each(|i| data.extend(i.to_le_bytes())),
+		BlkType::Int3(v) => v.iter().for_each(|i| data.extend(i.to_le_bytes())),
+		BlkType::Int4(v) => v.iter().for_each(|i| data.extend(i.to_le_bytes())),
+		BlkType::Long(v) => data.extend(v.to_le_bytes()),
+		// Inline values and strings are handled by the caller
+		_ => {},
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{fs, sync::Arc};
+
+	use crate::blk::{
+		binary_deserialize::parser::parse_blk,
+		binary_serialize::writer::SlimNameTable,
+		blk_string::blk_str,
+		blk_structure::BlkField,
+		blk_type::BlkType,
+		error::WriteError,
+		make_strict_test,
+		name_map::NameMap,
+	};
+
+	fn sample_nm() -> Arc<NameMap> {
+		let nm = fs::read("./samples/nm").unwrap();
+		Arc::new(NameMap::from_encoded_file(&nm).unwrap())
+	}
+
+	#[test]
+	fn fat_identical() {
+		let file = fs::read("./samples/section_fat.blk").unwrap();
+		let parsed = parse_blk(&file[1..], false, None).unwrap();
+		assert_eq!(parsed.to_fat_blk().unwrap(), file);
+	}
+
+	#[test]
+	fn fat_from_strict() {
+		let file = fs::read("./samples/section_fat.blk").unwrap();
+		assert_eq!(make_strict_test().to_fat_blk().unwrap(), file);
+	}
+
+	#[test]
+	fn fat_round_trip_netfile() {
+		let file = fs::read("./samples/encoded_11.blk").unwrap();
+		let parsed = parse_blk(&file[1..], false, None).unwrap();
+		let written = parsed.to_fat_blk().unwrap();
+		assert_eq!(parse_blk(&written[1..], false, None).unwrap(), parsed);
+	}
+
+	#[test]
+	fn merged_is_rejected() {
+		let mut blk = make_strict_test();
+		blk.insert_field(BlkField::Value(blk_str("int"), BlkType::Int(1)))
+			.unwrap();
+		blk.merge_fields().unwrap();
+		assert_eq!(
+			blk.to_fat_blk(),
+			Err(WriteError::MergedField("int".to_owned()))
+		);
+	}
+
+	#[test]
+	fn slim_identical() {
+		let file = fs::read("./samples/section_slim.blk").unwrap();
+		let nm = sample_nm();
+		let parsed = parse_blk(&file[1..], true, Some(nm.clone())).unwrap();
+		assert_eq!(parsed.to_slim_blk(nm).unwrap(), file);
+	}
+
+	#[test]
+	fn slim_missing_name() {
+		let mut blk = make_strict_test();
+		blk.insert_field(BlkField::Value(blk_str("unknown"), BlkType::Int(1)))
+			.unwrap();
+		assert_eq!(
+			blk.to_slim_blk(sample_nm()),
+			Err(WriteError::MissingName("unknown".to_owned()))
+		);
+	}
+
+	#[test]
+	fn slim_collect_names() {
+		let mut blk = make_strict_test();
+		// Values are written ahead of blocks, so the new field has to precede them to compare equal
+		if let BlkField::Struct(_, fields) = &mut blk {
+			fields.insert(
+				0,
+				BlkField::Value(blk_str("unknown"), BlkType::Str(blk_str("new value"))),
+			);
+		}
+
+		let base = sample_nm();
+		let mut names = SlimNameTable::collecting(base.clone());
+		let written = blk.to_slim_blk_with(&mut names).unwrap();
+		assert_eq!(
+			names.new_names(),
+			&[blk_str("new value"), blk_str("unknown")]
+		);
+
+		let nm = names.into_name_map();
+		assert_eq!(nm.parsed.len(), base.parsed.len() + 2);
+		assert_eq!(parse_blk(&written[1..], true, Some(nm)).unwrap(), blk);
+	}
+}
@@ -1,4 +1,4 @@
-use std::{io, io::Write};
+use std::{fmt, fmt::Display, io, io::Write};
 
 use color_eyre::Report;
 use serde::{Deserialize, Serialize, Serializer};
@@ -353,25 +353,108 @@ fn write_generic_array<'a, 'b, T: 'a + Copy, W: Write>(
 	Ok(())
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct BlkFormatting {
-	pub yesno_booleans:     bool,
-	pub assignment_spacing: bool,
+	pub yesno_booleans:            bool,
+	pub assignment_spacing:        bool,
+	/// Repeated once per nesting level
+	pub indent:                    Indent,
+	/// `name {` instead of `name{`
+	pub space_before_brace:        bool,
+	/// Empty lines separating a block from the fields next to it
+	pub blank_lines_around_blocks: usize,
+	/// Maximum amount of decimal places, trailing zeros are omitted
+	/// Uses the shortest representation that parses back into the same float when unset
+	pub float_precision:           Option<usize>,
+	/// Writes whole floats as `1.0` instead of `1`, matrices always do so
+	pub force_float_point:         bool,
+	pub key_quoting:               KeyQuoting,
+	/// Matrices are split into one row per line when the line would be longer, including indentation
+	pub matrix_line_width:         Option<usize>,
+}
+
+/// Indentation of one nesting level
+///
+/// Arbitrary strings are deliberately not supported, as anything but tabs or spaces would not read back as BLK
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Indent {
+	/// One tab per level
+	Tabs,
+	/// This many spaces per level
+	Spaces(u8),
+}
+
+impl Indent {
+	/// Indentation of `level` nesting levels
+	pub fn repeat(self, level: usize) -> String {
+		match self {
+			Indent::Tabs => "\t".repeat(level),
+			Indent::Spaces(n) => " ".repeat(n as usize * level),
+		}
+	}
+}
+
+impl Display for Indent {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.repeat(1))
+	}
+}
+
+/// When keys are put into quotes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeyQuoting {
+	/// Only keys that would not parse otherwise, such as those containing whitespace, `=` or braces
+	Required,
+	Always,
 }
 
 impl BlkFormatting {
 	pub const fn standard() -> Self {
 		Self {
-			yesno_booleans:     false,
-			assignment_spacing: true,
+			yesno_booleans:            false,
+			assignment_spacing:        true,
+			indent:                    Indent::Tabs,
+			space_before_brace:        true,
+			blank_lines_around_blocks: 0,
+			float_precision:           None,
+			force_float_point:         false,
+			key_quoting:               KeyQuoting::Required,
+			matrix_line_width:         None,
 		}
 	}
 
 	pub const fn compact() -> Self {
 		Self {
-			yesno_booleans:     true,
+			yesno_booleans: true,
 			assignment_spacing: false,
+			..Self::standard()
+		}
+	}
+
+	pub(crate) fn fmt_float(
+		&self,
+		f: &mut impl Write,
+		v: f32,
+		force_point: bool,
+	) -> io::Result<()> {
+		let mut s = match self.float_precision {
+			Some(precision) if v.is_finite() => {
+				let s = format!("{v:.precision$}");
+				if s.contains('.') {
+					s.trim_end_matches('0').trim_end_matches('.').to_owned()
+				} else {
+					s
+				}
+			},
+			_ => v.to_string(),
+		};
+		if s == "-0" {
+			s.remove(0);
+		}
+		if (force_point || self.force_float_point) && v.is_finite() && !s.contains('.') {
+			s.push_str(".0");
 		}
+		f.write_all(s.as_bytes())
 	}
 }
 
@@ -396,6 +479,11 @@ impl BlkType {
 		} else {
 			write!(f, "{}=", self.blk_type_name())?;
 		}
+		self.fmt_value_with(f, format)
+	}
+
+	/// Writes only the value, without its type and assignment
+	pub fn fmt_value_with(&self, f: &mut impl Write, format: BlkFormatting) -> io::Result<()> {
 		match self {
 			BlkType::Str(v) => {
 				write!(f, "\"{}\"", v)
@@ -408,31 +496,24 @@ impl BlkType {
 				write!(f, "{}, {}, {}", v[0], v[1], v[2])
 			},
 			BlkType::Int4(v) => {
-				write!(f, "{}, {}, {} {}", v[0], v[1], v[2], v[3])
-			},
-			BlkType::Long(v) => write!(f, "{v}"),
-			BlkType::Float(v) => write!(f, "{v}"),
-			BlkType::Float2(v) => {
-				write!(f, "{}, {}", v[0], v[1])
-			},
-			BlkType::Float3(v) => {
-				write!(f, "{}, {}, {}", v[0], v[1], v[2])
-			},
-			BlkType::Float4(v) => {
 				write!(f, "{}, {}, {}, {}", v[0], v[1], v[2], v[3])
 			},
+			BlkType::Long(v) => write!(f, "{v}"),
+			BlkType::Float(v) => format.fmt_float(f, *v, false),
+			BlkType::Float2(v) => write_floats(f, v, format, false),
+			BlkType::Float3(v) => write_floats(f, v, format, false),
+			BlkType::Float4(v) => write_floats(f, v.as_slice(), format, false),
 			BlkType::Float12(v) => {
-				let chunks = v.as_chunks::<3>().0.iter();
-				let len = chunks.len();
 				write!(f, "[")?;
-				for (i, chunk) in chunks.enumerate() {
-					if (1..len).contains(&i) {
+				for (i, row) in v.as_chunks::<3>().0.iter().enumerate() {
+					if i != 0 {
 						write!(f, " ")?;
 					}
-					write!(f, "{chunk:?}")?;
+					write!(f, "[")?;
+					write_floats(f, row, format, true)?;
+					write!(f, "]")?;
 				}
-				write!(f, "]")?;
-				Ok(())
+				write!(f, "]")
 			},
 			// 											yes/no or true/false
 			BlkType::Bool(v) => write!(
@@ -444,13 +525,29 @@ impl BlkType {
 					if *v { "true" } else { "false" }
 				}
 			),
+			// Channels are stored in binary byte order (BGRA), text is written as RGBA
 			BlkType::Color { r, g, b, a } => {
-				write!(f, "{r}, {g}, {b}, {a}")
+				write!(f, "{b}, {g}, {r}, {a}")
 			},
 		}
 	}
 }
 
+fn write_floats(
+	f: &mut impl Write,
+	values: &[f32],
+	format: BlkFormatting,
+	force_point: bool,
+) -> io::Result<()> {
+	for (i, v) in values.iter().enumerate() {
+		if i != 0 {
+			write!(f, ", ")?;
+		}
+		format.fmt_float(f, *v, force_point)?;
+	}
+	Ok(())
+}
+
 #[cfg(test)]
 mod test {
 	use crate::blk::{
@@ -0,0 +1,492 @@
+use std::fmt::{Display, Formatter};
+
+use indexmap::IndexMap;
+use serde::{Serialize, Serializer, ser::SerializeMap};
+
+use crate::blk::{
+	blk_string::BlkString,
+	blk_structure::BlkField,
+	blk_type::{BlkFormatting, BlkType},
+	plaintext_serialize::{
+		json::{TypedEntry, TypedValue},
+		value_text,
+	},
+};
+
+/// Single difference between two trees, see [`BlkField::diff`]
+///
+/// Paths are `/` separated and index repeated keys as in `weapon[1]/bullet`, like [`BlkField::get_field`].
+/// Added, changed and moved fields use their index in the new tree, removed fields the one in the old tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlkChange {
+	Added {
+		path:  String,
+		field: BlkField,
+	},
+	Removed {
+		path:  String,
+		field: BlkField,
+	},
+	/// Value that changed, including its type
+	Changed {
+		path: String,
+		old:  BlkType,
+		new:  BlkType,
+	},
+	/// Field that kept its content but changed its position among its siblings, only reported when order-sensitive
+	Moved {
+		path: String,
+		from: usize,
+		to:   usize,
+	},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffOptions {
+	/// Pairs repeated keys by position and reports moved fields, otherwise fields are matched by name and content
+	pub order_sensitive: bool,
+	/// Largest difference between floats that still counts as equal
+	pub float_epsilon:   f32,
+}
+
+impl Default for DiffOptions {
+	fn default() -> Self {
+		Self {
+			order_sensitive: false,
+			float_epsilon:   0.0,
+		}
+	}
+}
+
+impl BlkChange {
+	pub fn path(&self) -> &str {
+		match self {
+			BlkChange::Added { path, .. }
+			| BlkChange::Removed { path, .. }
+			| BlkChange::Changed { path, .. }
+			| BlkChange::Moved { path, .. } => path,
+		}
+	}
+}
+
+impl BlkField {
+	/// Structural difference between two trees, ignoring field order, see [`BlkField::diff_with`]
+	pub fn diff(old: &BlkField, new: &BlkField) -> Vec<BlkChange> {
+		Self::diff_with(old, new, DiffOptions::default())
+	}
+
+	/// Structural difference between two trees
+	///
+	/// Fields are matched by name, so reordering them is not a change unless [`DiffOptions::order_sensitive`] is set.
+	/// Repeated keys are matched to an equal counterpart first, any left over are compared in order
+	pub fn diff_with(old: &BlkField, new: &BlkField, options: DiffOptions) -> Vec<BlkChange> {
+		let mut changes = vec![];
+		match (old, new) {
+			(
+				BlkField::Struct(_, old_fields) | BlkField::Merged(_, old_fields),
+				BlkField::Struct(_, new_fields) | BlkField::Merged(_, new_fields),
+			) => diff_fields(old_fields, new_fields, "", options, &mut changes),
+			_ => diff_pair(
+				old,
+				new,
+				&old.get_name(),
+				&new.get_name(),
+				options,
+				&mut changes,
+			),
+		}
+		changes
+	}
+}
+
+fn diff_fields(
+	old: &[BlkField],
+	new: &[BlkField],
+	path: &str,
+	options: DiffOptions,
+	out: &mut Vec<BlkChange>,
+) {
+	let old = flattened(old);
+	let new = flattened(new);
+
+	// Positions of each name in both trees, in order of first appearance
+	let mut names: IndexMap<BlkString, (Vec<usize>, Vec<usize>)> = IndexMap::new();
+	for (i, field) in old.iter().enumerate() {
+		names.entry(field.get_name()).or_default().0.push(i);
+	}
+	for (i, field) in new.iter().enumerate() {
+		names.entry(field.get_name()).or_default().1.push(i);
+	}
+
+	// Matched positions across all names, for detecting moves
+	let mut pairs = vec![];
+	for (name, (olds, news)) in &names {
+		let segment = |positions: &[usize], i: usize| segment(path, name, positions, i);
+
+		let (mut unmatched_old, mut unmatched_new) = (vec![], news.clone());
+		if options.order_sensitive {
+			unmatched_old = olds.clone();
+		} else {
+			for &o in olds {
+				match unmatched_new
+					.iter()
+					.position(|&n| fields_equal(old[o], new[n], options))
+				{
+					Some(n) => pairs.push((o, unmatched_new.remove(n))),
+					None => unmatched_old.push(o),
+				}
+			}
+		}
+		let paired = unmatched_old.len().min(unmatched_new.len());
+		for (&o, &n) in unmatched_old.iter().zip(&unmatched_new) {
+			pairs.push((o, n));
+			diff_pair(
+				old[o],
+				new[n],
+				&segment(olds, o),
+				&segment(news, n),
+				options,
+				out,
+			);
+		}
+		for &o in &unmatched_old[paired..] {
+			out.push(BlkChange::Removed {
+				path:  segment(olds, o),
+				field: old[o].clone(),
+			});
+		}
+		for &n in &unmatched_new[paired..] {
+			out.push(BlkChange::Added {
+				path:  segment(news, n),
+				field: new[n].clone(),
+			});
+		}
+	}
+
+	if options.order_sensitive {
+		pairs.sort_unstable();
+		let kept = longest_increasing(&pairs.iter().map(|e| e.1).collect::<Vec<_>>());
+		for (i, &(from, to)) in pairs.iter().enumerate() {
+			if !kept[i] {
+				let name = new[to].get_name();
+				out.push(BlkChange::Moved {
+					path: segment(path, &name, &names[&name].1, to),
+					from,
+					to,
+				});
+			}
+		}
+	}
+}
+
+// Compares two fields with the same name
+fn diff_pair(
+	old: &BlkField,
+	new: &BlkField,
+	old_path: &str,
+	new_path: &str,
+	options: DiffOptions,
+	out: &mut Vec<BlkChange>,
+) {
+	match (old, new) {
+		(BlkField::Value(_, old_value), BlkField::Value(_, new_value)) => {
+			if !values_equal(old_value, new_value, options.float_epsilon) {
+				out.push(BlkChange::Changed {
+					path: new_path.to_owned(),
+					old:  old_value.clone(),
+					new:  new_value.clone(),
+				});
+			}
+		},
+		(BlkField::Value(..), _) | (_, BlkField::Value(..)) => {
+			out.push(BlkChange::Removed {
+				path:  old_path.to_owned(),
+				field: old.clone(),
+			});
+			out.push(BlkChange::Added {
+				path:  new_path.to_owned(),
+				field: new.clone(),
+			});
+		},
+		_ => diff_fields(old.fields(), new.fields(), new_path, options, out),
+	}
+}
+
+pub(crate) fn fields_equal(old: &BlkField, new: &BlkField, options: DiffOptions) -> bool {
+	let mut changes = vec![];
+	diff_pair(old, new, "", "", options, &mut changes);
+	changes.is_empty()
+}
+
+fn values_equal(old: &BlkType, new: &BlkType, epsilon: f32) -> bool {
+	// Identical infinities and NaN are equal, although their difference is NaN
+	let close = |a: &[f32], b: &[f32]| {
+		a.iter()
+			.zip(b)
+			.all(|(a, b)| a == b || (a.is_nan() && b.is_nan()) || (a - b).abs() <= epsilon)
+	};
+	match (old, new) {
+		(BlkType::Float(a), BlkType::Float(b)) => close(&[*a], &[*b]),
+		(BlkType::Float2(a), BlkType::Float2(b)) => close(a, b),
+		(BlkType::Float3(a), BlkType::Float3(b)) => close(a, b),
+		(BlkType::Float4(a), BlkType::Float4(b)) => close(&a[..], &b[..]),
+		(BlkType::Float12(a), BlkType::Float12(b)) => close(&a[..], &b[..]),
+		_ => old == new,
+	}
+}
+
+// Expands merged arrays back into the repeated keys they stem from
+pub(crate) fn flattened(fields: &[BlkField]) -> Vec<&BlkField> {
+	let mut out = Vec::with_capacity(fields.len());
+	for field in fields {
+		match field {
+			BlkField::Merged(_, merged) => out.extend(merged),
+			field => out.push(field),
+		}
+	}
+	out
+}
+
+// Marks the elements that are part of a longest strictly increasing subsequence
+pub(crate) fn longest_increasing(values: &[usize]) -> Vec<bool> {
+	// Index of the last element of the best subsequence of each length, and each element's predecessor
+	let mut tails: Vec<usize> = vec![];
+	let mut previous = vec![None; values.len()];
+	for (i, &value) in values.iter().enumerate() {
+		let len = tails.partition_point(|&t| values[t] < value);
+		previous[i] = len.checked_sub(1).map(|e| tails[e]);
+		if len == tails.len() {
+			tails.push(i);
+		} else {
+			tails[len] = i;
+		}
+	}
+	let mut kept = vec![false; values.len()];
+	let mut current = tails.last().copied();
+	while let Some(i) = current {
+		kept[i] = true;
+		current = previous[i];
+	}
+	kept
+}
+
+// Path of the field at position `i`, indexed if `positions` holds more than one field of that name
+fn segment(path: &str, name: &BlkString, positions: &[usize], i: usize) -> String {
+	let index =
+		(positions.len() > 1).then(|| positions.iter().position(|&e| e == i).unwrap_or_default());
+	child_path(path, name, index)
+}
+
+pub(crate) fn child_path(path: &str, name: &str, index: Option<usize>) -> String {
+	let segment = match index {
+		Some(k) => format!("{name}[{k}]"),
+		None => name.to_owned(),
+	};
+	if path.is_empty() {
+		segment
+	} else {
+		format!("{path}/{segment}")
+	}
+}
+
+/// One line per change, such as `~ engine/power:r = 1000 -> 1200`
+impl Display for BlkChange {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let text = |value: &BlkType| value_text(value, BlkFormatting::standard());
+		let field = |f: &mut Formatter<'_>, field: &BlkField| match field {
+			BlkField::Value(_, value) => write!(f, ":{} = {}", value.blk_type_name(), text(value)),
+			_ => write!(f, " {{{} fields}}", field.fields().len()),
+		};
+		match self {
+			BlkChange::Added { path, field: added } => {
+				write!(f, "+ {path}")?;
+				field(f, added)
+			},
+			BlkChange::Removed {
+				path,
+				field: removed,
+			} => {
+				write!(f, "- {path}")?;
+				field(f, removed)
+			},
+			BlkChange::Changed { path, old, new } if old.blk_type_name() == new.blk_type_name() => {
+				write!(
+					f,
+					"~ {path}:{} = {} -> {}",
+					new.blk_type_name(),
+					text(old),
+					text(new)
+				)
+			},
+			BlkChange::Changed { path, old, new } => write!(
+				f,
+				"~ {path}:{} = {} -> {path}:{} = {}",
+				old.blk_type_name(),
+				text(old),
+				new.blk_type_name(),
+				text(new)
+			),
+			BlkChange::Moved { path, from, to } => write!(f, "> {path} moved from {from} to {to}"),
+		}
+	}
+}
+
+/// Objects tagged by `kind`, with fields and values in the lossless form of [`BlkField::as_typed_json_streaming`]
+impl Serialize for BlkChange {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(None)?;
+		match self {
+			BlkChange::Added { path, field } => {
+				map.serialize_entry("kind", "added")?;
+				map.serialize_entry("path", path)?;
+				map.serialize_entry("field", &TypedEntry(field))?;
+			},
+			BlkChange::Removed { path, field } => {
+				map.serialize_entry("kind", "removed")?;
+				map.serialize_entry("path", path)?;
+				map.serialize_entry("field", &TypedEntry(field))?;
+			},
+			BlkChange::Changed { path, old, new } => {
+				map.serialize_entry("kind", "changed")?;
+				map.serialize_entry("path", path)?;
+				map.serialize_entry("old_type", old.blk_type_name())?;
+				map.serialize_entry("old", &TypedValue(old))?;
+				map.serialize_entry("new_type", new.blk_type_name())?;
+				map.serialize_entry("new", &TypedValue(new))?;
+			},
+			BlkChange::Moved { path, from, to } => {
+				map.serialize_entry("kind", "moved")?;
+				map.serialize_entry("path", path)?;
+				map.serialize_entry("from", from)?;
+				map.serialize_entry("to", to)?;
+			},
+		}
+		map.end()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::blk::{
+		blk_string::blk_str,
+		blk_structure::BlkField,
+		blk_type::BlkType,
+		diff::{BlkChange, DiffOptions},
+		plaintext_deserialize::deserialize_blk,
+	};
+
+	fn diff(old: &str, new: &str, options: DiffOptions) -> Vec<String> {
+		BlkField::diff_with(
+			&deserialize_blk(old).unwrap(),
+			&deserialize_blk(new).unwrap(),
+			options,
+		)
+		.iter()
+		.map(ToString::to_string)
+		.collect()
+	}
+
+	#[test]
+	fn unordered() {
+		let old = "speed:r=1\nengine { power:i=1000; mass:r=500 }\nweapon { name:t=\"a\" }\nweapon { name:t=\"b\" }";
+		let new = "engine { mass:r=500.5; power:i=1000 }\nweapon { name:t=\"b\" }\nweapon { name:t=\"c\" }\nspeed:i=1\narmor:b=yes";
+		assert_eq!(
+			diff(old, new, DiffOptions::default()),
+			[
+				"~ speed:r = 1 -> speed:i = 1",
+				"~ engine/mass:r = 500 -> 500.5",
+				"~ weapon[1]/name:t = \"a\" -> \"c\"",
+				"+ armor:b = true",
+			]
+		);
+		assert!(diff(old, old, DiffOptions::default()).is_empty());
+
+		let tolerant = DiffOptions {
+			float_epsilon: 0.5,
+			..DiffOptions::default()
+		};
+		assert_eq!(diff(old, new, tolerant).len(), 3);
+	}
+
+	#[test]
+	fn ordered() {
+		let old = "a:i=1\nb:i=2\nc:i=3\nx { v:i=1 }\nx { v:i=2 }";
+		let new = "c:i=3\na:i=1\nb:i=2\nx { v:i=2 }\nx { v:i=1 }\ny {}";
+		let options = DiffOptions {
+			order_sensitive: true,
+			..DiffOptions::default()
+		};
+		assert_eq!(
+			diff(old, new, options),
+			[
+				"~ x[0]/v:i = 1 -> 2",
+				"~ x[1]/v:i = 2 -> 1",
+				"+ y {0 fields}",
+				"> c moved from 2 to 0",
+			]
+		);
+		assert_eq!(diff(old, new, DiffOptions::default()), ["+ y {0 fields}"]);
+	}
+
+	#[test]
+	fn non_finite() {
+		let old = "a:r=inf\nb:p2=-inf, 1\nc:r=NaN\nd:r=1";
+		assert!(diff(old, old, DiffOptions::default()).is_empty());
+		assert_eq!(
+			diff(
+				old,
+				"a:r=-inf\nb:p2=-inf, 1\nc:r=1\nd:r=inf",
+				DiffOptions::default()
+			),
+			[
+				"~ a:r = inf -> -inf",
+				"~ c:r = NaN -> 1",
+				"~ d:r = 1 -> inf",
+			]
+		);
+	}
+
+	#[test]
+	fn kinds() {
+		let changes = BlkField::diff(
+			&deserialize_blk("a:i=1\nb { c:i=1 }").unwrap(),
+			&deserialize_blk("a { }\nb:i=1").unwrap(),
+		);
+		assert_eq!(
+			changes,
+			[
+				BlkChange::Removed {
+					path:  "a".to_owned(),
+					field: BlkField::Value(blk_str("a"), BlkType::Int(1)),
+				},
+				BlkChange::Added {
+					path:  "a".to_owned(),
+					field: BlkField::Struct(blk_str("a"), vec![]),
+				},
+				BlkChange::Removed {
+					path:  "b".to_owned(),
+					field: BlkField::Struct(
+						blk_str("b"),
+						vec![BlkField::Value(blk_str("c"), BlkType::Int(1))]
+					),
+				},
+				BlkChange::Added {
+					path:  "b".to_owned(),
+					field: BlkField::Value(blk_str("b"), BlkType::Int(1)),
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn json() {
+		let changes = BlkField::diff(
+			&deserialize_blk("speed:r=1\nold:b=yes").unwrap(),
+			&deserialize_blk("speed:r=2\nnew { x:i=1 }").unwrap(),
+		);
+		assert_eq!(
+			serde_json::to_string(&changes).unwrap(),
+			r#"[{"kind":"changed","path":"speed","old_type":"r","old":1.0,"new_type":"r","new":2.0},{"kind":"removed","path":"old","field":{"old:b":true}},{"kind":"added","path":"new","field":{"new":[{"x:i":1}]}}]"#
+		);
+	}
+}
@@ -1,5 +1,6 @@
-use std::{ops::Range, string::FromUtf8Error};
+use std::{fmt::Display, ops::Range, string::FromUtf8Error};
 
+use color_eyre::{Report, Section};
 use thiserror::Error;
 
 use crate::blk::blk_block_hierarchy::BlkBlockBuilderError;
@@ -49,3 +50,240 @@ pub enum ParseError {
 	#[error("Custom: {0}")]
 	Custom(String),
 }
+
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum WriteError {
+	#[error("Merged field {0} cannot be written, as BLK has no array type")]
+	MergedField(String),
+
+	#[error("The root of a BLK file has to be a struct")]
+	RootNotStruct,
+
+	#[error("Name {0:?} is not contained in the shared name map")]
+	MissingName(String),
+
+	#[error("{section} with {count} entries exceeds the maximum of {max}")]
+	SectionOverflow {
+		section: &'static str,
+		count:   usize,
+		max:     usize,
+	},
+}
+
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum NmDigestError {
+	#[error(
+		"Dictionary digest mismatch, the nm header expects {} but the dictionary hashes to {}. The nm and dict most likely stem from different versions",
+		hex(.expected),
+		hex(.found)
+	)]
+	DictMismatch {
+		expected: [u8; 32],
+		found:    [u8; 32],
+	},
+
+	#[error("The nm header references dictionary {} but no dictionary was found", hex(.expected))]
+	MissingDict { expected: [u8; 32] },
+}
+
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum DeserializeError {
+	#[error("{0}")]
+	Custom(String),
+
+	/// Error nested inside a field, the path is `/` separated
+	#[error("{path}: {message}")]
+	InField { path: String, message: String },
+}
+
+impl DeserializeError {
+	/// Prepends the name of the field the error occurred in
+	pub(crate) fn in_field(self, field: &str) -> Self {
+		match self {
+			Self::Custom(message) => Self::InField {
+				path: field.to_owned(),
+				message,
+			},
+			Self::InField { path, message } => Self::InField {
+				path: format!("{field}/{path}"),
+				message,
+			},
+		}
+	}
+}
+
+impl serde::de::Error for DeserializeError {
+	fn custom<T: Display>(msg: T) -> Self {
+		Self::Custom(msg.to_string())
+	}
+}
+
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum SerializeError {
+	#[error("{0}")]
+	Custom(String),
+
+	#[error("The root has to serialize into a struct or map")]
+	RootNotStruct,
+
+	#[error("Keys have to be strings, chars or integers")]
+	InvalidKey,
+
+	/// Error nested inside a field, the path is `/` separated
+	#[error("{path}: {message}")]
+	InField { path: String, message: String },
+}
+
+impl SerializeError {
+	/// Prepends the name of the field the error occurred in
+	pub(crate) fn in_field(self, field: &str) -> Self {
+		match self {
+			Self::InField { path, message } => Self::InField {
+				path: format!("{field}/{path}"),
+				message,
+			},
+			other => Self::InField {
+				path:    field.to_owned(),
+				message: other.to_string(),
+			},
+		}
+	}
+}
+
+impl serde::ser::Error for SerializeError {
+	fn custom<T: Display>(msg: T) -> Self {
+		Self::Custom(msg.to_string())
+	}
+}
+
+/// Error from the path based accessors such as [`BlkField::get`](crate::blk::blk_structure::BlkField::get)
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum GetError {
+	#[error("{path} does not exist")]
+	Missing { path: String },
+
+	#[error("{path} is a value, it has no field {field}")]
+	NotAStruct { path: String, field: String },
+
+	#[error("{path} is a struct, not a value")]
+	NotAValue { path: String },
+
+	#[error("{path} has type {found}, expected {expected}")]
+	WrongType {
+		path:     String,
+		expected: &'static str,
+		found:    &'static str,
+	},
+}
+
+/// Invalid query, see [`Query::parse`](crate::blk::query::Query::parse)
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum QueryError {
+	#[error("Empty step at position {0}")]
+	EmptyStep(usize),
+
+	#[error("Unclosed [ at position {0}")]
+	UnclosedBracket(usize),
+
+	#[error("Expected / at position {0}")]
+	ExpectedSeparator(usize),
+
+	#[error("Invalid predicate {0:?}")]
+	InvalidPredicate(String),
+}
+
+/// Error in BLK text, locating the offending input
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+#[error("{message} at line {line}, column {column}")]
+pub struct TextParseError {
+	message:     String,
+	span:        Range<usize>,
+	line:        usize,
+	column:      usize,
+	// Full line the span starts in, without its line break
+	source_line: String,
+	// Characters of the span within that line
+	caret_width: usize,
+}
+
+impl TextParseError {
+	/// Creates an error for the byte range `span` of `input`
+	pub(crate) fn new(input: &[u8], span: Range<usize>, message: String) -> Self {
+		let start = span.start.min(input.len());
+		let line_start = input[..start]
+			.iter()
+			.rposition(|&b| b == b'\n')
+			.map_or(0, |i| i + 1);
+		let line_end = input[start..]
+			.iter()
+			.position(|&b| b == b'\n')
+			.map_or(input.len(), |i| start + i);
+		let source_line = String::from_utf8_lossy(&input[line_start..line_end]);
+		let caret_end = span.end.clamp(start, line_end);
+		Self {
+			message,
+			span: start..span.end.clamp(start, input.len()),
+			line: input[..start].iter().filter(|&&b| b == b'\n').count() + 1,
+			column: String::from_utf8_lossy(&input[line_start..start])
+				.chars()
+				.count() + 1,
+			source_line: source_line.trim_end_matches('\r').to_owned(),
+			caret_width: String::from_utf8_lossy(&input[start..caret_end])
+				.chars()
+				.count()
+				.max(1),
+		}
+	}
+
+	pub fn message(&self) -> &str {
+		&self.message
+	}
+
+	/// Byte range of the input the error refers to, empty when pointing between characters
+	pub fn span(&self) -> Range<usize> {
+		self.span.clone()
+	}
+
+	/// One-based line of the span start
+	pub fn line(&self) -> usize {
+		self.line
+	}
+
+	/// One-based column of the span start, counted in characters
+	pub fn column(&self) -> usize {
+		self.column
+	}
+
+	/// Renders the offending line with a caret underneath the span
+	/// ```text
+	///   |
+	/// 2 |   speed:r = 1,5
+	///   |              ^
+	/// ```
+	pub fn snippet(&self) -> String {
+		let number = self.line.to_string();
+		let gutter = " ".repeat(number.len());
+		// Tabs are kept so the caret lines up regardless of tab width
+		let offset: String = self
+			.source_line
+			.chars()
+			.take(self.column - 1)
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect();
+		format!(
+			"{gutter} |\n{number} | {}\n{gutter} | {offset}{}",
+			self.source_line,
+			"^".repeat(self.caret_width)
+		)
+	}
+
+	/// Converts into a report, attaching the snippet as section
+	pub fn into_report(self) -> Report {
+		let snippet = self.snippet();
+		Report::new(self).section(snippet)
+	}
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
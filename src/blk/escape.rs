@@ -0,0 +1,131 @@
+/// Escape character of BLK text, as used by the game's parser
+pub const ESCAPE: char = '~';
+
+/// Whether a key has to be quoted to be parsed back as the same name
+pub fn key_needs_quotes(key: &str) -> bool {
+	key.is_empty()
+		|| key.contains(|c: char| {
+			c.is_whitespace()
+				|| c.is_control()
+				|| matches!(c, '=' | '{' | '}' | '"' | '\'' | ';' | ESCAPE)
+		}) || key.contains("//")
+		|| key.contains("/*")
+}
+
+/// Quotes a key or string value, escaping everything the parser treats specially
+///
+/// Double quotes are preferred, single quotes are used when they avoid escaping.
+/// `~` is escaped as `~~`, quotes as `~"` or `~'`, and line breaks and tabs as `~n`, `~r` and `~t`.
+pub fn quote(s: &str) -> String {
+	let quote = if s.contains('"') && !s.contains('\'') {
+		'\''
+	} else {
+		'"'
+	};
+	let mut out = String::with_capacity(s.len() + 2);
+	out.push(quote);
+	for c in s.chars() {
+		match c {
+			'\n' => out.push_str("~n"),
+			'\r' => out.push_str("~r"),
+			'\t' => out.push_str("~t"),
+			c if c == ESCAPE || c == quote => {
+				out.push(ESCAPE);
+				out.push(c);
+			},
+			c => out.push(c),
+		}
+	}
+	out.push(quote);
+	out
+}
+
+/// Resolves the character following [`ESCAPE`], unknown escapes yield the character itself
+pub fn unescape(escaped: u8) -> u8 {
+	match escaped {
+		b'n' => b'\n',
+		b'r' => b'\r',
+		b't' => b'\t',
+		other => other,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use proptest::prelude::*;
+
+	use crate::blk::{
+		blk_string::blk_str,
+		blk_structure::BlkField,
+		blk_type::{BlkFormatting, BlkType, KeyQuoting},
+		escape::quote,
+		plaintext_deserialize::deserialize_blk,
+	};
+
+	#[test]
+	fn quoting() {
+		assert_eq!(quote("plain"), r#""plain""#);
+		assert_eq!(quote(r#"say "hi""#), r#"'say "hi"'"#);
+		assert_eq!(quote(r#"both " and '"#), r#""both ~" and '""#);
+		assert_eq!(quote("a~b\nc:d\t"), r#""a~~b~nc:d~t""#);
+	}
+
+	#[test]
+	fn parses_escapes() {
+		let parsed = deserialize_blk(r#""k~"ey":t = "line~nbreak ~~ ~' ~q""#).unwrap();
+		assert_eq!(
+			parsed,
+			BlkField::Struct(
+				blk_str("root"),
+				vec![BlkField::Value(
+					blk_str("k\"ey"),
+					BlkType::Str(blk_str("line\nbreak ~ ' q"))
+				)]
+			)
+		);
+	}
+
+	fn float() -> impl Strategy<Value = f32> {
+		// NaN never compares equal, so it cannot round-trip in a test
+		any::<f32>().prop_filter("NaN", |v| !v.is_nan())
+	}
+
+	fn value() -> impl Strategy<Value = BlkType> {
+		prop_oneof![
+			any::<String>().prop_map(|s| BlkType::Str(blk_str(s))),
+			any::<i32>().prop_map(BlkType::Int),
+			any::<[i32; 2]>().prop_map(BlkType::Int2),
+			any::<[i32; 3]>().prop_map(BlkType::Int3),
+			any::<[i32; 4]>().prop_map(|v| BlkType::Int4(Box::new(v))),
+			any::<i64>().prop_map(BlkType::Long),
+			float().prop_map(BlkType::Float),
+			[float(), float()].prop_map(BlkType::Float2),
+			[float(), float(), float()].prop_map(BlkType::Float3),
+			[float(), float(), float(), float()].prop_map(|v| BlkType::Float4(Box::new(v))),
+			prop::array::uniform12(float()).prop_map(|v| BlkType::Float12(Box::new(v))),
+			any::<bool>().prop_map(BlkType::Bool),
+			any::<[u8; 4]>().prop_map(|[r, g, b, a]| BlkType::Color { r, g, b, a }),
+		]
+	}
+
+	fn field() -> impl Strategy<Value = BlkField> {
+		let leaf = (any::<String>(), value()).prop_map(|(k, v)| BlkField::Value(blk_str(k), v));
+		leaf.prop_recursive(4, 64, 8, |inner| {
+			(any::<String>(), prop::collection::vec(inner, 0..8))
+				.prop_map(|(k, fields)| BlkField::Struct(blk_str(k), fields))
+		})
+	}
+
+	proptest! {
+		#[test]
+		fn text_round_trip(fields in prop::collection::vec(field(), 0..8), always_quote: bool) {
+			let root = BlkField::Struct(blk_str("root"), fields);
+			let format = BlkFormatting {
+				key_quoting: if always_quote { KeyQuoting::Always } else { KeyQuoting::Required },
+				..BlkFormatting::standard()
+			};
+			let text = root.as_blk_text(format).unwrap();
+			prop_assert_eq!(deserialize_blk(&text).unwrap(), root, "{}", text);
+		}
+	}
+}
@@ -0,0 +1,281 @@
+use color_eyre::{
+	Report,
+	eyre::{Context, bail, eyre},
+};
+
+use crate::blk::{blk_string::blk_str, blk_structure::BlkField, blk_type::BlkType};
+
+/// Name of the field that `include "path"` directives are parsed into
+pub const INCLUDE_FIELD: &str = "@include";
+
+/// Source of BLK files that includes are loaded from
+pub trait BlkLoader {
+	/// Loads the file at `path`, which is relative to the root and uses `/` as separator
+	fn load(&self, path: &str) -> Result<BlkField, Report>;
+}
+
+impl<F: Fn(&str) -> Result<BlkField, Report>> BlkLoader for F {
+	fn load(&self, path: &str) -> Result<BlkField, Report> {
+		self(path)
+	}
+}
+
+/// Loads the file at `path` and resolves it into the tree the engine would see
+///
+/// Includes are inlined where they occur, after which [`BlkField::apply_directives`] runs over the whole tree.
+/// Include paths are relative to the including file, unless prefixed with `#` which makes them relative to the root.
+pub fn resolve(loader: &impl BlkLoader, path: &str) -> Result<BlkField, Report> {
+	let root = load(loader, path, &mut vec![])?;
+	let mut resolved = BlkField::Struct(blk_str("root"), root);
+	resolved.apply_directives();
+	Ok(resolved)
+}
+
+/// Same as [`resolve`], for a file that was already parsed and is located at `path`
+pub fn resolve_field(
+	loader: &impl BlkLoader,
+	field: BlkField,
+	path: &str,
+) -> Result<BlkField, Report> {
+	let mut stack = vec![path.to_owned()];
+	let mut resolved = inline(loader, field, path, &mut stack)?;
+	resolved.apply_directives();
+	Ok(resolved)
+}
+
+// Loads a file, returning the fields of its root with all includes inlined
+fn load(
+	loader: &impl BlkLoader,
+	path: &str,
+	stack: &mut Vec<String>,
+) -> Result<Vec<BlkField>, Report> {
+	if stack.iter().any(|e| e == path) {
+		bail!("Include cycle: {} -> {path}", stack.join(" -> "));
+	}
+	stack.push(path.to_owned());
+	let field = loader
+		.load(path)
+		.with_context(|| format!("loading {path}"))?;
+	let BlkField::Struct(_, fields) = inline(loader, field, path, stack)? else {
+		bail!("{path} did not load into a struct");
+	};
+	stack.pop();
+	Ok(fields)
+}
+
+fn inline(
+	loader: &impl BlkLoader,
+	field: BlkField,
+	path: &str,
+	stack: &mut Vec<String>,
+) -> Result<BlkField, Report> {
+	let BlkField::Struct(name, fields) = field else {
+		return Ok(field);
+	};
+	let mut out = Vec::with_capacity(fields.len());
+	for field in fields {
+		match field {
+			BlkField::Value(key, BlkType::Str(include)) if key.as_str() == INCLUDE_FIELD => {
+				let included = include_path(path, &include)?;
+				out.extend(load(loader, &included, stack)?);
+			},
+			field => out.push(inline(loader, field, path, stack)?),
+		}
+	}
+	Ok(BlkField::Struct(name, out))
+}
+
+/// Resolves an include relative to the file containing it
+pub fn include_path(including: &str, include: &str) -> Result<String, Report> {
+	let (base, relative) = match include.strip_prefix('#') {
+		Some(rest) => ("", rest),
+		None => (
+			including.rsplit_once('/').map_or("", |(dir, _)| dir),
+			include,
+		),
+	};
+	let mut parts: Vec<_> = base.split('/').filter(|e| !e.is_empty()).collect();
+	for segment in relative.split(['/', '\\']) {
+		match segment {
+			"" | "." => {},
+			".." => {
+				parts
+					.pop()
+					.ok_or_else(|| eyre!("Include {include} in {including} leaves the root"))?;
+			},
+			segment => parts.push(segment),
+		}
+	}
+	Ok(parts.join("/"))
+}
+
+impl BlkField {
+	/// Applies the directives Dagor supports in field names, in order of appearance
+	///
+	/// - `@override:name`, or `override:name` as used by [`BlkField::apply_overrides`], replaces the first field called `name`.
+	///   Overriding a block with another block merges them, where fields inside the override replace their namesakes or are appended.
+	///   Overrides without a matching field are dropped
+	/// - `@delete:name` removes all preceding fields called `name`
+	/// - `@append:name` appends the fields of a block to the last block called `name`, creating it if missing.
+	///   Values are appended as a regular field
+	pub fn apply_directives(&mut self) {
+		if let BlkField::Struct(_, fields) | BlkField::Merged(_, fields) = self {
+			*fields = apply_all(std::mem::take(fields));
+		}
+	}
+}
+
+fn apply_all(fields: Vec<BlkField>) -> Vec<BlkField> {
+	let mut out = Vec::with_capacity(fields.len());
+	for field in fields {
+		apply(&mut out, field, false);
+	}
+	out
+}
+
+// Adds a field to the already processed fields of a block
+// Within an overriding block, plain fields override their namesakes as well
+fn apply(out: &mut Vec<BlkField>, mut field: BlkField, overriding: bool) {
+	let name = field.get_name();
+	let (directive, target) = match name.split_once(':') {
+		Some((directive @ ("@override" | "override" | "@delete" | "@append"), target)) => {
+			(Some(directive), target)
+		},
+		_ => (None, name.as_str()),
+	};
+	if directive.is_some() {
+		field.set_name(blk_str(target));
+	}
+
+	match directive {
+		Some("@delete") => out.retain(|e| e.get_name().as_str() != target),
+		Some("@override" | "override") => override_field(out, field, false),
+		None if overriding => override_field(out, field, true),
+		Some("@append") => match field {
+			BlkField::Struct(_, fields) => {
+				let existing = out.iter_mut().rev().find_map(|e| match e {
+					BlkField::Struct(name, children) if name.as_str() == target => Some(children),
+					_ => None,
+				});
+				match existing {
+					Some(children) => {
+						for field in fields {
+							apply(children, field, false);
+						}
+					},
+					None => out.push(BlkField::Struct(blk_str(target), apply_all(fields))),
+				}
+			},
+			field => out.push(field),
+		},
+		_ => out.push(normalized(field)),
+	}
+}
+
+fn override_field(out: &mut Vec<BlkField>, field: BlkField, append_missing: bool) {
+	let existing = out.iter_mut().find(|e| e.get_name() == field.get_name());
+	match (existing, field) {
+		(Some(BlkField::Struct(_, children)), BlkField::Struct(_, fields)) => {
+			for field in fields {
+				apply(children, field, true);
+			}
+		},
+		(Some(existing), field) => *existing = normalized(field),
+		(None, field) if append_missing => out.push(normalized(field)),
+		(None, _) => {},
+	}
+}
+
+fn normalized(mut field: BlkField) -> BlkField {
+	field.apply_directives();
+	field
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::HashMap;
+
+	use color_eyre::{Report, eyre::eyre};
+
+	use crate::blk::{
+		blk_structure::BlkField,
+		include::{include_path, resolve},
+		plaintext_deserialize::deserialize_blk,
+	};
+
+	fn loader(files: &[(&str, &str)]) -> impl Fn(&str) -> Result<BlkField, Report> {
+		let files: HashMap<String, String> = files
+			.iter()
+			.map(|(k, v)| (k.to_string(), v.to_string()))
+			.collect();
+		move |path| {
+			Ok(deserialize_blk(
+				files.get(path).ok_or_else(|| eyre!("{path} not found"))?,
+			)?)
+		}
+	}
+
+	#[test]
+	fn includes() {
+		let loader = loader(&[
+			(
+				"config/main.blk",
+				"speed:r=1\ninclude \"parts/engine.blk\"\nweapon { include \"#common/gun.blk\" }",
+			),
+			("config/parts/engine.blk", "power:i=1500\n"),
+			(
+				"common/gun.blk",
+				"include \"../config/parts/engine.blk\"\ncaliber:r=12.7",
+			),
+		]);
+		assert_eq!(
+			resolve(&loader, "config/main.blk").unwrap(),
+			deserialize_blk("speed:r=1\npower:i=1500\nweapon { power:i=1500; caliber:r=12.7 }")
+				.unwrap()
+		);
+	}
+
+	#[test]
+	fn cycle() {
+		let loader = loader(&[
+			("a.blk", "include \"b.blk\""),
+			("b.blk", "x { include \"a.blk\" }"),
+		]);
+		let err = resolve(&loader, "a.blk").unwrap_err();
+		assert_eq!(err.to_string(), "Include cycle: a.blk -> b.blk -> a.blk");
+	}
+
+	#[test]
+	fn directives() {
+		let loader = loader(&[
+			(
+				"base.blk",
+				"engine { power:i=1000; mass:r=500 }\nweapon { name:t=\"a\" }\nweapon { name:t=\"b\" }\nspeed:r=1",
+			),
+			(
+				"mod.blk",
+				"include \"base.blk\"\n\
+				@override:engine { power:i=2000; turbo:b=yes }\n\
+				@delete:weapon:b=yes\n\
+				@append:engine { power:i=1 }\n\
+				override:speed:r=2\n\
+				@override:missing:r=3",
+			),
+		]);
+		assert_eq!(
+			resolve(&loader, "mod.blk").unwrap(),
+			deserialize_blk(
+				"engine { power:i=2000; mass:r=500; turbo:b=yes; power:i=1 }\nspeed:r=2"
+			)
+			.unwrap()
+		);
+	}
+
+	#[test]
+	fn paths() {
+		assert_eq!(include_path("a/b/c.blk", "d.blk").unwrap(), "a/b/d.blk");
+		assert_eq!(include_path("a/b/c.blk", "../d.blk").unwrap(), "a/d.blk");
+		assert_eq!(include_path("a/b/c.blk", "#/x/d.blk").unwrap(), "x/d.blk");
+		assert!(include_path("c.blk", "../d.blk").is_err());
+	}
+}
@@ -40,9 +40,31 @@ pub fn uleb128_offset(bytes: &[u8], buffer_idx: &mut usize) -> Result<usize, Par
 	return Ok(value);
 }
 
+/// Appends `value` ULEB encoded to the buffer, the inverse of [`uleb128`]
+#[inline]
+pub fn write_uleb128(buf: &mut Vec<u8>, mut value: usize) {
+	const MASK: u8 = 1 << 7;
+
+	loop {
+		// The lowest 7 bits of the remaining value
+		let bits = (value & (MASK as usize - 1)) as u8;
+		value >>= 7;
+
+		// Set the continuation bit for as long as there are bits remaining
+		if value == 0 {
+			buf.push(bits);
+			return;
+		}
+		buf.push(bits | MASK);
+	}
+}
+
 #[cfg(test)]
 mod test {
-	use crate::blk::{error::ParseError, leb128::uleb128};
+	use crate::blk::{
+		error::ParseError,
+		leb128::{uleb128, write_uleb128},
+	};
 
 	#[test]
 	fn empty() {
@@ -66,4 +88,13 @@ mod test {
 	fn aol_extended() {
 		assert_eq!(uleb128(&[u8::MAX, 42]), Ok((2, 5503)))
 	}
+
+	#[test]
+	fn encode_round_trip() {
+		for value in [0, 1, 42, 127, 128, 5503, 300_000, u32::MAX as usize] {
+			let mut buf = vec![];
+			write_uleb128(&mut buf, value);
+			assert_eq!(uleb128(&buf), Ok((buf.len(), value)));
+		}
+	}
 }
@@ -0,0 +1,391 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	iter::once,
+};
+
+use indexmap::IndexMap;
+
+use crate::blk::{
+	blk_string::BlkString,
+	blk_structure::BlkField,
+	diff::{DiffOptions, child_path, fields_equal, flattened, longest_increasing},
+};
+
+/// Outcome of [`BlkField::merge3`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeResult {
+	/// Merged tree, using our side wherever a conflict occurred
+	pub merged:    BlkField,
+	pub conflicts: Vec<MergeConflict>,
+}
+
+/// Field both sides changed in incompatible ways, absent sides are `None`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+	/// Path in the same form as [`BlkChange`](crate::blk::diff::BlkChange) uses
+	pub path:   String,
+	pub kind:   ConflictKind,
+	pub base:   Option<BlkField>,
+	pub ours:   Option<BlkField>,
+	pub theirs: Option<BlkField>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+	/// Both sides changed the field to different values
+	BothModified,
+	/// Both sides added the field with different values
+	BothAdded,
+	/// We removed the field while they modified it
+	DeletedByOurs,
+	/// They removed the field while we modified it
+	DeletedByTheirs,
+}
+
+impl BlkField {
+	/// Three-way merge of two trees that both derive from `base`, such as two mods patching one file
+	///
+	/// Changes only one side made are taken as is, blocks both sides changed are merged field by field.
+	/// Repeated keys keep their order, their elements are aligned with base like a line based diff, so one side inserting an element does not shift the edits of the other.
+	/// Directives such as `override:` are merged like regular fields, to merge their effect instead resolve them first using [`BlkField::apply_directives`]
+	pub fn merge3(base: &BlkField, ours: &BlkField, theirs: &BlkField) -> MergeResult {
+		let mut conflicts = vec![];
+		let fields = merge_fields(
+			base.fields(),
+			ours.fields(),
+			theirs.fields(),
+			"",
+			&mut conflicts,
+		);
+		MergeResult {
+			merged: BlkField::Struct(ours.get_name(), fields),
+			conflicts,
+		}
+	}
+}
+
+fn merge_fields(
+	base: &[BlkField],
+	ours: &[BlkField],
+	theirs: &[BlkField],
+	path: &str,
+	conflicts: &mut Vec<MergeConflict>,
+) -> Vec<BlkField> {
+	let (base, ours, theirs) = (flattened(base), flattened(ours), flattened(theirs));
+
+	// Fields of each name as base, ours and theirs, new names are placed in our order first
+	let mut names: IndexMap<BlkString, [Vec<&BlkField>; 3]> = IndexMap::new();
+	for (side, fields) in [(1, &ours), (2, &theirs), (0, &base)] {
+		for field in fields {
+			names.entry(field.get_name()).or_default()[side].push(field);
+		}
+	}
+
+	let mut merged: IndexMap<BlkString, VecDeque<BlkField>> = names
+		.iter()
+		.map(|(name, [b, o, t])| (name.clone(), merge_group(name, b, o, t, path, conflicts)))
+		.collect();
+
+	// Merged fields take the place of our fields of the same name, any extra follow the last of them
+	let mut remaining: HashMap<BlkString, usize> = HashMap::new();
+	for field in &ours {
+		*remaining.entry(field.get_name()).or_default() += 1;
+	}
+	let mut out = Vec::with_capacity(ours.len());
+	for field in &ours {
+		let name = field.get_name();
+		let group = merged.get_mut(&name).expect("every name was merged");
+		out.extend(group.pop_front());
+		let left = remaining.get_mut(&name).expect("counted above");
+		*left -= 1;
+		if *left == 0 {
+			out.extend(group.drain(..));
+		}
+	}
+	// Names we never had
+	out.extend(merged.into_values().flatten());
+	out
+}
+
+// Merges all fields of one name
+fn merge_group(
+	name: &str,
+	base: &[&BlkField],
+	ours: &[&BlkField],
+	theirs: &[&BlkField],
+	path: &str,
+	conflicts: &mut Vec<MergeConflict>,
+) -> VecDeque<BlkField> {
+	let same = |a: &[&BlkField], b: &[&BlkField]| {
+		a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(Some(a), Some(b)))
+	};
+	let cloned = |fields: &[&BlkField]| fields.iter().map(|&e| e.clone()).collect();
+	if same(ours, theirs) || same(theirs, base) {
+		return cloned(ours);
+	}
+	if same(ours, base) {
+		return cloned(theirs);
+	}
+
+	// Both sides changed the group, elements are aligned with base so inserting or removing one does not shift the others
+	let (ours_matched, ours_inserted) = align(base, ours);
+	let (theirs_matched, theirs_inserted) = align(base, theirs);
+	let indexed = base.len().max(ours.len()).max(theirs.len()) > 1;
+	let mut out = VecDeque::new();
+	for k in 0..=base.len() {
+		// Insertions of both sides in the same place are paired up, differing ones conflict
+		let (o, t) = (&ours_inserted[k], &theirs_inserted[k]);
+		for i in 0..o.len().max(t.len()) {
+			let field = merge_field(
+				None,
+				o.get(i).map(|&n| ours[n]),
+				t.get(i).map(|&n| theirs[n]),
+				&child_path(path, name, indexed.then_some(out.len())),
+				conflicts,
+			);
+			out.extend(field);
+		}
+		if let Some(&base) = base.get(k) {
+			let field = merge_field(
+				Some(base),
+				ours_matched[k].map(|n| ours[n]),
+				theirs_matched[k].map(|n| theirs[n]),
+				&child_path(path, name, indexed.then_some(out.len())),
+				conflicts,
+			);
+			out.extend(field);
+		}
+	}
+	out
+}
+
+// Counterpart of each base element in `side`, and the elements `side` inserted in front of each base element (the last entry being the end)
+//
+// Equal elements that keep their relative order are anchors, the remaining ones between two anchors are paired by position
+fn align(base: &[&BlkField], side: &[&BlkField]) -> (Vec<Option<usize>>, Vec<Vec<usize>>) {
+	let mut used = vec![false; side.len()];
+	let mut pairs = vec![];
+	for (o, &field) in base.iter().enumerate() {
+		if let Some(n) = (0..side.len()).find(|&n| !used[n] && equal(Some(field), Some(side[n]))) {
+			used[n] = true;
+			pairs.push((o, n));
+		}
+	}
+	let kept = longest_increasing(&pairs.iter().map(|e| e.1).collect::<Vec<_>>());
+	let anchors = pairs
+		.into_iter()
+		.zip(kept)
+		.filter_map(|(pair, kept)| kept.then_some(pair))
+		.chain(once((base.len(), side.len())));
+
+	let mut matched = vec![None; base.len()];
+	let mut inserted = vec![vec![]; base.len() + 1];
+	let (mut o, mut n) = (0, 0);
+	for (anchor_o, anchor_n) in anchors {
+		let paired = (anchor_o - o).min(anchor_n - n);
+		for i in 0..paired {
+			matched[o + i] = Some(n + i);
+		}
+		inserted[anchor_o].extend(n + paired..anchor_n);
+		if anchor_o < base.len() {
+			matched[anchor_o] = Some(anchor_n);
+		}
+		(o, n) = (anchor_o + 1, anchor_n + 1);
+	}
+	(matched, inserted)
+}
+
+fn merge_field(
+	base: Option<&BlkField>,
+	ours: Option<&BlkField>,
+	theirs: Option<&BlkField>,
+	path: &str,
+	conflicts: &mut Vec<MergeConflict>,
+) -> Option<BlkField> {
+	if equal(ours, theirs) || equal(theirs, base) {
+		return ours.cloned();
+	}
+	if equal(ours, base) {
+		return theirs.cloned();
+	}
+
+	match (base, ours, theirs) {
+		(
+			None | Some(BlkField::Struct(..)),
+			Some(ours @ BlkField::Struct(..)),
+			Some(theirs @ BlkField::Struct(..)),
+		) => {
+			let fields = merge_fields(
+				base.map_or(&[][..], BlkField::fields),
+				ours.fields(),
+				theirs.fields(),
+				path,
+				conflicts,
+			);
+			Some(BlkField::Struct(ours.get_name(), fields))
+		},
+		_ => {
+			let kind = match (base, ours, theirs) {
+				(None, ..) => ConflictKind::BothAdded,
+				(_, None, _) => ConflictKind::DeletedByOurs,
+				(_, _, None) => ConflictKind::DeletedByTheirs,
+				_ => ConflictKind::BothModified,
+			};
+			conflicts.push(MergeConflict {
+				path: path.to_owned(),
+				kind,
+				base: base.cloned(),
+				ours: ours.cloned(),
+				theirs: theirs.cloned(),
+			});
+			ours.cloned()
+		},
+	}
+}
+
+fn equal(a: Option<&BlkField>, b: Option<&BlkField>) -> bool {
+	match (a, b) {
+		(None, None) => true,
+		(Some(a), Some(b)) => fields_equal(
+			a,
+			b,
+			DiffOptions {
+				order_sensitive: true,
+				..DiffOptions::default()
+			},
+		),
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::blk::{
+		blk_structure::BlkField,
+		merge::ConflictKind,
+		plaintext_deserialize::deserialize_blk,
+	};
+
+	fn merge(base: &str, ours: &str, theirs: &str) -> (BlkField, Vec<(String, ConflictKind)>) {
+		let result = BlkField::merge3(
+			&deserialize_blk(base).unwrap(),
+			&deserialize_blk(ours).unwrap(),
+			&deserialize_blk(theirs).unwrap(),
+		);
+		(
+			result.merged,
+			result
+				.conflicts
+				.into_iter()
+				.map(|e| (e.path, e.kind))
+				.collect(),
+		)
+	}
+
+	#[test]
+	fn clean() {
+		let (merged, conflicts) = merge(
+			"speed:r=1\nengine { power:i=1000; mass:r=500 }\nold:b=yes",
+			"speed:r=2\nengine { power:i=1200; mass:r=500 }\nold:b=yes\nours:i=1",
+			"speed:r=1\nengine { mass:r=450; power:i=1000 }\ntheirs:i=2",
+		);
+		assert!(conflicts.is_empty());
+		assert_eq!(
+			merged,
+			deserialize_blk("speed:r=2\nengine { power:i=1200; mass:r=450 }\nours:i=1\ntheirs:i=2")
+				.unwrap()
+		);
+	}
+
+	#[test]
+	fn conflicts() {
+		let (merged, conflicts) = merge(
+			"speed:r=1\nengine { power:i=1000 }\narmor { front:i=50 }",
+			"speed:r=2\narmor { front:i=60 }\nadded:i=1",
+			"speed:r=3\nengine { power:i=1100 }\nadded:i=2",
+		);
+		assert_eq!(
+			conflicts,
+			[
+				("speed".to_owned(), ConflictKind::BothModified),
+				("armor".to_owned(), ConflictKind::DeletedByTheirs),
+				("added".to_owned(), ConflictKind::BothAdded),
+				("engine".to_owned(), ConflictKind::DeletedByOurs),
+			]
+		);
+		// Conflicts resolve to our side
+		assert_eq!(
+			merged,
+			deserialize_blk("speed:r=2\narmor { front:i=60 }\nadded:i=1").unwrap()
+		);
+	}
+
+	#[test]
+	fn repeated_keys() {
+		let base = "preset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=1 }";
+		let (merged, conflicts) = merge(
+			base,
+			"preset { name:t=\"a\"; gun:i=2 }\npreset { name:t=\"b\"; gun:i=1 }\npreset { name:t=\"c\" }",
+			"preset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=3 }",
+		);
+		assert!(conflicts.is_empty());
+		assert_eq!(
+			merged,
+			deserialize_blk(
+				"preset { name:t=\"a\"; gun:i=2 }\npreset { name:t=\"b\"; gun:i=3 }\npreset { name:t=\"c\" }"
+			)
+			.unwrap()
+		);
+
+		let (_, conflicts) = merge(
+			base,
+			"preset { name:t=\"a\"; gun:i=2 }\npreset { name:t=\"b\"; gun:i=1 }",
+			"preset { name:t=\"a\"; gun:i=3 }\npreset { name:t=\"b\"; gun:i=1 }",
+		);
+		assert_eq!(
+			conflicts,
+			[("preset[0]/gun".to_owned(), ConflictKind::BothModified)]
+		);
+	}
+
+	#[test]
+	fn repeated_keys_shifted() {
+		let base = "preset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=1 }";
+		let (merged, conflicts) = merge(
+			base,
+			"preset { name:t=\"x\" }\npreset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=1 }",
+			"preset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=3 }",
+		);
+		assert!(conflicts.is_empty());
+		assert_eq!(
+			merged,
+			deserialize_blk(
+				"preset { name:t=\"x\" }\npreset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=3 }"
+			)
+			.unwrap()
+		);
+
+		// Removing an element while the other side inserts one in its place
+		let (merged, conflicts) = merge(
+			base,
+			"preset { name:t=\"b\"; gun:i=2 }",
+			"preset { name:t=\"y\" }\npreset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=1 }",
+		);
+		assert!(conflicts.is_empty());
+		assert_eq!(
+			merged,
+			deserialize_blk("preset { name:t=\"y\" }\npreset { name:t=\"b\"; gun:i=2 }").unwrap()
+		);
+
+		// Both inserting different elements in the same place merges them, conflicting where they differ
+		let (_, conflicts) = merge(
+			base,
+			"preset { name:t=\"x\" }\npreset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=1 }",
+			"preset { name:t=\"y\" }\npreset { name:t=\"a\"; gun:i=1 }\npreset { name:t=\"b\"; gun:i=3 }",
+		);
+		assert_eq!(
+			conflicts,
+			[("preset[0]/name".to_owned(), ConflictKind::BothAdded)]
+		);
+	}
+}
@@ -143,7 +143,7 @@ use std::{
 	},
 };
 
-pub use ::zstd::dict::DecoderDictionary;
+pub use ::zstd::dict::{DecoderDictionary, EncoderDictionary};
 use blk_string::blk_str;
 use cfg_if::cfg_if;
 use color_eyre::{Report, eyre::bail};
@@ -173,7 +173,7 @@ pub mod error;
 /// One-byte file header that each blk file begins with
 pub mod file;
 
-/// Utility function to decode ULEB128 encoded files
+/// Utility functions to encode and decode ULEB128 integers
 /// <https://en.wikipedia.org/wiki/LEB128>
 pub mod leb128;
 
@@ -190,17 +190,43 @@ cfg_if! {
 /// Collection of macros and functions used in all BLK modules
 pub mod util;
 
-/// Zstandard unpacking functionality
+/// Zstandard packing and unpacking functionality
 pub mod zstd;
 
 /// Implementations for serializing into human readable text formats from internal representation
 pub mod plaintext_serialize;
 
 /// Implementations for deserializing into internal representation format from text
-mod plaintext_deserialize;
+pub mod plaintext_deserialize;
+
+/// Serde integration, mapping BLK onto Rust types
+pub mod serde_blk;
+
+pub use serde_blk::{from_blk, to_blk};
+
+/// Resolving `include` directives and override semantics across files
+pub mod include;
+
+/// Escaping of keys and strings in BLK text
+pub mod escape;
+
+/// Typed, non-panicking getters and path based editing of [`BlkField`]
+pub mod accessors;
+
+/// Queries selecting all fields matching a pattern, with wildcards, recursive descent and predicates
+pub mod query;
+
+/// Structural differences between two trees, for comparing files across versions
+pub mod diff;
+
+/// Three-way merging of trees, for combining changes from two sources
+pub mod merge;
 
 /// Implementation for deserializing internal representation to binary form
 pub mod binary_deserialize;
+
+/// Implementation for serializing internal representation to binary form
+pub mod binary_serialize;
 mod blk_string;
 
 #[allow(dead_code)]
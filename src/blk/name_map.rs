@@ -2,23 +2,109 @@ use std::{io::Read, isize, iter::once, ops::Add, sync::Arc};
 
 use color_eyre::{Report, eyre::ContextCompat};
 use itertools::Itertools;
+use sha1_smol::Sha1;
+use sha2::{Digest, Sha256};
 use zstd::Decoder;
 
-use crate::blk::{blk_string::BlkString, leb128::uleb128_offset};
+use crate::blk::{
+	blk_string::BlkString,
+	error::NmDigestError,
+	leb128::{uleb128_offset, write_uleb128},
+};
 
 /// A name map is a collection of shared strings across an entire VROMF file
 /// Its usually in the top-level directory and called `nm` or in the binary vromf : `0xff 0x3f nm` (prefixed with a pair of seemingly random bytes)
+///
+/// The encoded file is laid out as follows:
+/// |Names digest|Dict digest|Names|
+/// |-|-|-|
+/// |Digest over the names, see [`NameMap::names_digest`]|SHA-256 of the accompanying `.dict` file|ZSTD compressed names|
+/// |8 bytes|32 bytes|Remainder|
 #[derive(Clone, Debug)]
 pub struct NameMap {
 	pub binary: Vec<u8>,
 	pub parsed: Arc<Vec<BlkString>>,
 }
 
+/// Digests stored in the header of an encoded `nm` file
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct NmDigests {
+	pub names: [u8; 8],
+	pub dict:  [u8; 32],
+}
+
+/// Outcome of [`NmDigests::validate_names`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NamesDigestCheck {
+	/// The digest is the SHA1 over these names
+	Matches,
+	/// The digest was computed some other way, or over other names
+	UnknownScheme,
+}
+
+impl NmDigests {
+	pub fn from_encoded_file(file: &[u8]) -> Result<Self, Report> {
+		let header = file.get(0..40).context(format!(
+			"File out of bounds for range 0..40, found len: {}",
+			file.len()
+		))?;
+		Ok(Self {
+			names: header[0..8].try_into()?,
+			dict:  header[8..40].try_into()?,
+		})
+	}
+
+	/// Checks whether the names digest is the one computed by [`NameMap::names_digest`]
+	///
+	/// As the scheme used by the remaining game files is unknown, a mismatch cannot tell an nm file mixed up with other names apart from one of those
+	pub fn validate_names(&self, nm: &NameMap) -> NamesDigestCheck {
+		if nm.names_digest() == self.names {
+			NamesDigestCheck::Matches
+		} else {
+			NamesDigestCheck::UnknownScheme
+		}
+	}
+
+	/// Checks that the dict digest matches the SHA-256 of the dictionary, see [`NameMap::dict_digest`]
+	pub fn validate_dict(&self, dict_digest: Option<[u8; 32]>) -> Result<(), NmDigestError> {
+		match dict_digest {
+			Some(found) if found != self.dict => Err(NmDigestError::DictMismatch {
+				expected: self.dict,
+				found,
+			}),
+			// A zeroed digest means the nm was not created alongside a dictionary
+			None if self.dict != [0; 32] => Err(NmDigestError::MissingDict {
+				expected: self.dict,
+			}),
+			_ => Ok(()),
+		}
+	}
+}
+
 impl NameMap {
 	pub fn idx_parsed(&self, idx: usize) -> Option<&BlkString> {
 		self.parsed.get(idx)
 	}
 
+	/// Builds the decoded form of a name map from its names, as they would be referenced by index
+	pub fn from_names(names: Vec<BlkString>) -> Self {
+		let mut section = vec![];
+		for name in &names {
+			section.extend_from_slice(name.as_bytes());
+			section.push(0);
+		}
+
+		let mut binary = Vec::with_capacity(section.len() + 8);
+		write_uleb128(&mut binary, names.len());
+		write_uleb128(&mut binary, section.len());
+		binary.extend_from_slice(&section);
+
+		Self {
+			binary,
+			parsed: Arc::new(names),
+		}
+	}
+
 	pub fn from_encoded_file(file: &[u8]) -> Result<Self, Report> {
 		let decoded = Self::decode_nm_file(file)?;
 
@@ -43,6 +129,62 @@ impl NameMap {
 		Ok(out)
 	}
 
+	/// Encodes into the `nm` file format, the inverse of [`Self::decode_nm_file`]
+	/// `dict` is the ZSTD dictionary shipped in the same VROMF, its digest is zeroed when there is none
+	/// The names digest is the best-effort [`Self::names_digest`], prefer [`Self::encode_nm_file_like`] when re-encoding a game file
+	pub fn encode_nm_file(&self, dict: Option<&[u8]>) -> Result<Vec<u8>, Report> {
+		self.encode_with_names_digest(dict, self.names_digest())
+	}
+
+	/// Same as [`Self::encode_nm_file`], keeping the names digest of the `original` encoded file
+	///
+	/// The names digest is only recomputed when names changed and the original is known to use [`Self::names_digest`],
+	/// otherwise the original digest is the best guess, as its scheme is unknown
+	pub fn encode_nm_file_like(
+		&self,
+		dict: Option<&[u8]>,
+		original: &[u8],
+	) -> Result<Vec<u8>, Report> {
+		let digests = NmDigests::from_encoded_file(original)?;
+		let original = Self::from_encoded_file(original)?;
+		let names_digest = if original.binary != self.binary
+			&& digests.validate_names(&original) == NamesDigestCheck::Matches
+		{
+			self.names_digest()
+		} else {
+			digests.names
+		};
+		self.encode_with_names_digest(dict, names_digest)
+	}
+
+	fn encode_with_names_digest(
+		&self,
+		dict: Option<&[u8]>,
+		names_digest: [u8; 8],
+	) -> Result<Vec<u8>, Report> {
+		let compressed = zstd::encode_all(self.binary.as_slice(), 0)?;
+
+		let mut out = Vec::with_capacity(40 + compressed.len());
+		out.extend_from_slice(&names_digest);
+		out.extend_from_slice(&dict.map(Self::dict_digest).unwrap_or_default());
+		out.extend_from_slice(&compressed);
+		Ok(out)
+	}
+
+	/// Best-effort names digest: the leading 8 bytes of the SHA1 over the decoded names
+	///
+	/// This is not known to be how the game computes it. Some game files match it (`samples/nm`), others store a digest of unknown origin,
+	/// regardless of whether they ship a dictionary (`samples/rendist/nm`, `char.vromfs.bin`).
+	pub fn names_digest(&self) -> [u8; 8] {
+		let digest = Sha1::from(&self.binary).digest().bytes();
+		digest[..8].try_into().expect("Infallible")
+	}
+
+	/// SHA-256 over the dictionary file, which the game also uses as the `.dict` filename
+	pub fn dict_digest(dict: &[u8]) -> [u8; 32] {
+		Sha256::digest(dict).into()
+	}
+
 	pub fn parse_name_section(file: &[u8]) -> Vec<BlkString> {
 		once(-1_isize)
 			.chain(memchr::memchr_iter(b'\0', file).map(|u| u as isize))
@@ -74,7 +216,12 @@ impl NameMap {
 mod test {
 	use std::fs;
 
-	use crate::blk::{leb128::uleb128, name_map::NameMap};
+	use crate::blk::{
+		blk_string::{BlkString, blk_str},
+		error::NmDigestError,
+		leb128::uleb128,
+		name_map::{NameMap, NamesDigestCheck, NmDigests},
+	};
 
 	#[test]
 	fn test_any_stream() {
@@ -88,6 +235,104 @@ mod test {
 		)
 	}
 
+	#[test]
+	fn from_names() {
+		let file = fs::read("./samples/nm").unwrap();
+		let nm = NameMap::from_encoded_file(&file).unwrap();
+		let rebuilt = NameMap::from_names(nm.parsed.as_ref().clone());
+		assert_eq!(rebuilt.binary, nm.binary);
+	}
+
+	#[test]
+	fn encode_nm_file() {
+		let file = fs::read("./samples/nm").unwrap();
+		let dict = fs::read(
+			"./samples/bfb732560ad45234690acad246d7b14c2f25ad418a146e5e7ef68ba3386a315c.dict",
+		)
+		.unwrap();
+		let nm = NameMap::from_encoded_file(&file).unwrap();
+
+		let encoded = nm.encode_nm_file(Some(&dict)).unwrap();
+		// Both digests match the original, the compressed stream may differ depending on compression settings
+		assert_eq!(&encoded[..40], &file[..40]);
+		assert_eq!(NameMap::decode_nm_file(&encoded).unwrap(), nm.binary);
+	}
+
+	#[test]
+	fn validate_digests() {
+		let file = fs::read("./samples/nm").unwrap();
+		let dict = fs::read(
+			"./samples/bfb732560ad45234690acad246d7b14c2f25ad418a146e5e7ef68ba3386a315c.dict",
+		)
+		.unwrap();
+		let nm = NameMap::from_encoded_file(&file).unwrap();
+		let digests = NmDigests::from_encoded_file(&file).unwrap();
+
+		assert_eq!(digests.validate_names(&nm), NamesDigestCheck::Matches);
+		digests
+			.validate_dict(Some(NameMap::dict_digest(&dict)))
+			.unwrap();
+
+		let other = NameMap::from_names(vec![BlkString::new("other")]);
+		assert_eq!(
+			digests.validate_names(&other),
+			NamesDigestCheck::UnknownScheme
+		);
+		assert!(matches!(
+			digests.validate_dict(Some([0; 32])),
+			Err(NmDigestError::DictMismatch { .. })
+		));
+		assert!(matches!(
+			digests.validate_dict(None),
+			Err(NmDigestError::MissingDict { .. })
+		));
+	}
+
+	#[test]
+	fn encode_like_original() {
+		let file = fs::read("./samples/rendist/nm").unwrap();
+		let nm = NameMap::from_encoded_file(&file).unwrap();
+
+		// The digest of unknown origin is kept, as the names are unchanged
+		let encoded = nm.encode_nm_file_like(None, &file).unwrap();
+		assert_eq!(&encoded[..8], &file[..8]);
+		assert_ne!(&nm.encode_nm_file(None).unwrap()[..8], &file[..8]);
+		assert_eq!(NameMap::decode_nm_file(&encoded).unwrap(), nm.binary);
+
+		// Also when names were added, as the scheme to recompute it is unknown
+		let mut names = nm.parsed.as_ref().clone();
+		names.push(blk_str("brand_new"));
+		let added = NameMap::from_names(names);
+		assert_eq!(
+			&added.encode_nm_file_like(None, &file).unwrap()[..8],
+			&file[..8]
+		);
+
+		// Files known to use the SHA1 over the names get it recomputed
+		let file = fs::read("./samples/nm").unwrap();
+		let mut names = NameMap::from_encoded_file(&file)
+			.unwrap()
+			.parsed
+			.as_ref()
+			.clone();
+		names.push(blk_str("brand_new"));
+		let added = NameMap::from_names(names);
+		assert_eq!(
+			added.encode_nm_file_like(None, &file).unwrap()[..8],
+			added.names_digest()
+		);
+	}
+
+	#[test]
+	fn unknown_names_digest() {
+		// Shipped with a dictionary, yet the names digest is not the SHA1 over the names
+		let file = fs::read("./samples/rendist/nm").unwrap();
+		let nm = NameMap::from_encoded_file(&file).unwrap();
+		let digests = NmDigests::from_encoded_file(&file).unwrap();
+		assert_ne!(digests.dict, [0; 32]);
+		assert_eq!(digests.validate_names(&nm), NamesDigestCheck::UnknownScheme);
+	}
+
 	#[test]
 	fn test_nm_file() {
 		let file = fs::read("./samples/nm").unwrap();
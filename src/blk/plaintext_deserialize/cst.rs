@@ -0,0 +1,678 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	mem,
+};
+
+use color_eyre::{
+	Report,
+	eyre::{bail, eyre},
+};
+
+use crate::blk::{
+	blk_string::blk_str,
+	blk_structure::BlkField,
+	blk_type::{BlkFormatting, BlkType, KeyQuoting},
+	error::TextParseError,
+	escape,
+	include::INCLUDE_FIELD,
+	plaintext_deserialize::Cursor,
+	plaintext_serialize::{escape_key, value_text},
+};
+
+/// BLK text parsed into a lossless tree, keeping comments, blank lines and indentation
+///
+/// Writing the document back using [`Display`] yields the input byte for byte, and edits only touch the text of the fields they change.
+/// Paths are `/` separated names as in [`BlkField::pointer`], selecting the first field of that name. The root block is the empty path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlkDocument {
+	root:  Body,
+	// Formatting of newly inserted fields, taken from the first occurrences in the document
+	style: Style,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Style {
+	indent_unit: String,
+	separator:   String,
+	assignment:  String,
+	open:        String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Body {
+	items:    Vec<Item>,
+	// Trivia after the last item, up to the closing brace or end of input
+	trailing: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Item {
+	// Whitespace, comments and `;` preceding the field
+	leading: String,
+	name:    String,
+	// Name as written, including quotes
+	key:     String,
+	kind:    ItemKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ItemKind {
+	Value {
+		// Text between name and type, such as `:`
+		separator:  String,
+		ty:         String,
+		// Text between type and value, such as ` = `
+		assignment: String,
+		raw:        String,
+		value:      BlkType,
+	},
+	Block {
+		// Text between name and opening brace, including the brace
+		open: String,
+		body: Body,
+	},
+}
+
+impl BlkDocument {
+	/// Parses text accepted by [`super::deserialize_blk`]
+	pub fn parse(input: &str) -> Result<Self, TextParseError> {
+		let mut c = Cursor {
+			at:    0,
+			inner: input.as_bytes(),
+		};
+		let root = parse_body(&mut c, input, true)?;
+		let mut style = Style {
+			indent_unit: "\t".to_owned(),
+			separator:   ":".to_owned(),
+			assignment:  " = ".to_owned(),
+			open:        " {".to_owned(),
+		};
+		if let Some(unit) = root.indent_unit("") {
+			style.indent_unit = unit.to_owned();
+		}
+		if let Some((separator, assignment)) = root.value_style() {
+			style.separator = separator.to_owned();
+			style.assignment = assignment.to_owned();
+		}
+		if let Some(open) = root.block_style() {
+			style.open = open.to_owned();
+		}
+		Ok(Self { root, style })
+	}
+
+	/// Converts the document into its internal representation, equal to what [`super::deserialize_blk`] returns
+	pub fn to_blk_field(&self) -> BlkField {
+		BlkField::Struct(blk_str("root"), self.root.fields())
+	}
+
+	/// Returns the value at `path`
+	pub fn value(&self, path: &str) -> Result<&BlkType, Report> {
+		let (parent, name) = split_path(path);
+		match &self.root.block(parent)?.item(name)?.kind {
+			ItemKind::Value { value, .. } => Ok(value),
+			ItemKind::Block { .. } => bail!("{name} is a block, not a value"),
+		}
+	}
+
+	/// Replaces the value at `path`, changing its type if required
+	/// The text is left untouched when the value is equal to the current one
+	pub fn set_value(&mut self, path: &str, new: BlkType) -> Result<(), Report> {
+		let (parent, name) = split_path(path);
+		let (body, _) = self.block_mut(parent)?;
+		let ItemKind::Value { ty, raw, value, .. } = &mut body.item_mut(name)?.kind else {
+			bail!("{name} is a block, not a value")
+		};
+		if *value == new {
+			return Ok(());
+		}
+		if ty.is_empty() {
+			let BlkType::Str(path) = &new else {
+				bail!("{name} is an include, it can only be set to a path")
+			};
+			*raw = escape::quote(path);
+			*value = new;
+			return Ok(());
+		}
+		let format = BlkFormatting {
+			// Keep the style booleans were written in
+			yesno_booleans: matches!(raw.as_str(), "yes" | "no" | "on" | "off"),
+			..BlkFormatting::standard()
+		};
+		*raw = value_text(&new, format);
+		*ty = new.blk_type_name().to_owned();
+		*value = new;
+		Ok(())
+	}
+
+	/// Appends a field to the block at `path`
+	pub fn insert_field(&mut self, path: &str, field: &BlkField) -> Result<(), Report> {
+		let len = self.root.block(path)?.items.len();
+		self.insert_field_at(path, len, field)
+	}
+
+	/// Inserts a field into the block at `path`, before the field currently at `index`
+	/// The new field is placed on its own line, matching the indentation of its siblings
+	pub fn insert_field_at(
+		&mut self,
+		path: &str,
+		index: usize,
+		field: &BlkField,
+	) -> Result<(), Report> {
+		let is_root = path.split('/').all(str::is_empty);
+		let style = self.style.clone();
+		let (body, indent) = self.block_mut(path)?;
+		if index > body.items.len() {
+			bail!(
+				"Index {index} is out of bounds for a block with {} fields",
+				body.items.len()
+			);
+		}
+
+		let mut item = Item::from_field(field, &indent, &style)?;
+		let was_empty = body.items.is_empty();
+		match body.items.get_mut(index) {
+			// Comments belonging to the next field stay above it
+			Some(next) => match next.leading.find('\n') {
+				Some(n) => {
+					item.leading = format!("{}\n{indent}", &next.leading[..n]);
+					next.leading.drain(..n);
+				},
+				None => item.leading = mem::replace(&mut next.leading, format!("\n{indent}")),
+			},
+			None if is_root && was_empty => {
+				item.leading = mem::take(&mut body.trailing);
+			},
+			None => {
+				item.leading = format!("\n{indent}");
+				// Closing brace of an empty block such as `name {}` moves onto its own line
+				if was_empty && !body.trailing.contains('\n') {
+					let outer = indent.strip_suffix(&style.indent_unit).unwrap_or_default();
+					body.trailing = format!("\n{outer}{}", body.trailing.trim_start());
+				}
+			},
+		}
+		body.items.insert(index, item);
+		Ok(())
+	}
+
+	/// Removes the value or block at `path`, along with the line it occupied
+	/// Comments preceding the field are kept
+	pub fn remove(&mut self, path: &str) -> Result<(), Report> {
+		let (parent, name) = split_path(path);
+		let (body, _) = self.block_mut(parent)?;
+		let index = body.position(name)?;
+		let removed = body.items.remove(index);
+		let next = match body.items.get_mut(index) {
+			Some(item) => &mut item.leading,
+			None => &mut body.trailing,
+		};
+		*next = join_trivia(&removed.leading, next, index != 0);
+		Ok(())
+	}
+
+	// Resolves a block and the indentation of its fields
+	fn block_mut(&mut self, path: &str) -> Result<(&mut Body, String), Report> {
+		let unit = &self.style.indent_unit;
+		let mut body = &mut self.root;
+		let mut indent = body.indent().unwrap_or_default().to_owned();
+		for segment in path.split('/').filter(|s| !s.is_empty()) {
+			let item = body.item_mut(segment)?;
+			let own = line_indent(&item.leading).unwrap_or(&indent).to_owned();
+			body = match &mut item.kind {
+				ItemKind::Block { body, .. } => body,
+				ItemKind::Value { .. } => bail!("{segment} is a value, not a block"),
+			};
+			indent = match body.indent() {
+				Some(indent) => indent.to_owned(),
+				None => own + unit,
+			};
+		}
+		Ok((body, indent))
+	}
+}
+
+impl Display for BlkDocument {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		self.root.write(f)
+	}
+}
+
+impl Body {
+	fn block(&self, path: &str) -> Result<&Body, Report> {
+		let mut body = self;
+		for segment in path.split('/').filter(|s| !s.is_empty()) {
+			body = match &body.item(segment)?.kind {
+				ItemKind::Block { body, .. } => body,
+				ItemKind::Value { .. } => bail!("{segment} is a value, not a block"),
+			};
+		}
+		Ok(body)
+	}
+
+	fn position(&self, name: &str) -> Result<usize, Report> {
+		self.items
+			.iter()
+			.position(|item| item.name == name)
+			.ok_or_else(|| eyre!("{name} does not exist"))
+	}
+
+	fn item(&self, name: &str) -> Result<&Item, Report> {
+		Ok(&self.items[self.position(name)?])
+	}
+
+	fn item_mut(&mut self, name: &str) -> Result<&mut Item, Report> {
+		let index = self.position(name)?;
+		Ok(&mut self.items[index])
+	}
+
+	/// Indentation of the first field that starts on its own line
+	fn indent(&self) -> Option<&str> {
+		self.items
+			.iter()
+			.find_map(|item| line_indent(&item.leading))
+	}
+
+	fn indent_unit(&self, outer: &str) -> Option<&str> {
+		self.items.iter().find_map(|item| {
+			let ItemKind::Block { body, .. } = &item.kind else {
+				return None;
+			};
+			let own = line_indent(&item.leading).unwrap_or(outer);
+			body.indent()
+				.and_then(|inner| inner.strip_prefix(own))
+				.filter(|unit| !unit.is_empty())
+				.or_else(|| body.indent_unit(own))
+		})
+	}
+
+	fn value_style(&self) -> Option<(&str, &str)> {
+		self.items.iter().find_map(|item| match &item.kind {
+			ItemKind::Value {
+				separator,
+				assignment,
+				..
+			} => Some((separator.as_str(), assignment.as_str())),
+			ItemKind::Block { body, .. } => body.value_style(),
+		})
+	}
+
+	fn block_style(&self) -> Option<&str> {
+		self.items.iter().find_map(|item| match &item.kind {
+			ItemKind::Block { open, .. } => Some(open.as_str()),
+			ItemKind::Value { .. } => None,
+		})
+	}
+
+	fn fields(&self) -> Vec<BlkField> {
+		self.items.iter().map(Item::to_field).collect()
+	}
+
+	fn write(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for item in &self.items {
+			item.write(f)?;
+		}
+		f.write_str(&self.trailing)
+	}
+}
+
+impl Item {
+	fn from_field(field: &BlkField, indent: &str, style: &Style) -> Result<Self, Report> {
+		Ok(match field {
+			BlkField::Value(name, BlkType::Str(path)) if name.as_str() == INCLUDE_FIELD => Self {
+				leading: String::new(),
+				name:    INCLUDE_FIELD.to_owned(),
+				key:     "include".to_owned(),
+				kind:    ItemKind::Value {
+					separator:  " ".to_owned(),
+					ty:         String::new(),
+					assignment: String::new(),
+					raw:        escape::quote(path),
+					value:      BlkType::Str(path.clone()),
+				},
+			},
+			BlkField::Value(name, value) => Self {
+				leading: String::new(),
+				name:    name.to_string(),
+				key:     escape_key(name, KeyQuoting::Required),
+				kind:    ItemKind::Value {
+					separator:  style.separator.clone(),
+					ty:         value.blk_type_name().to_owned(),
+					assignment: style.assignment.clone(),
+					raw:        value_text(value, BlkFormatting::standard()),
+					value:      value.clone(),
+				},
+			},
+			BlkField::Struct(name, fields) => {
+				let inner = format!("{indent}{}", style.indent_unit);
+				let items = fields
+					.iter()
+					.map(|field| {
+						let mut item = Self::from_field(field, &inner, style)?;
+						item.leading = format!("\n{inner}");
+						Ok(item)
+					})
+					.collect::<Result<_, Report>>()?;
+				Self {
+					leading: String::new(),
+					name:    name.to_string(),
+					key:     escape_key(name, KeyQuoting::Required),
+					kind:    ItemKind::Block {
+						open: style.open.clone(),
+						body: Body {
+							items,
+							trailing: format!("\n{indent}"),
+						},
+					},
+				}
+			},
+			BlkField::Merged(..) => {
+				bail!("Merged fields have no text representation, insert them one by one instead")
+			},
+		})
+	}
+
+	fn to_field(&self) -> BlkField {
+		match &self.kind {
+			ItemKind::Value { value, .. } => BlkField::Value(blk_str(&self.name), value.clone()),
+			ItemKind::Block { body, .. } => BlkField::Struct(blk_str(&self.name), body.fields()),
+		}
+	}
+
+	fn write(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.leading)?;
+		f.write_str(&self.key)?;
+		match &self.kind {
+			ItemKind::Value {
+				separator,
+				ty,
+				assignment,
+				raw,
+				..
+			} => {
+				f.write_str(separator)?;
+				f.write_str(ty)?;
+				f.write_str(assignment)?;
+				f.write_str(raw)
+			},
+			ItemKind::Block { open, body } => {
+				f.write_str(open)?;
+				body.write(f)?;
+				f.write_str("}")
+			},
+		}
+	}
+}
+
+fn parse_body(c: &mut Cursor, input: &str, is_root: bool) -> Result<Body, TextParseError> {
+	let mut items = vec![];
+	loop {
+		let start = c.at;
+		c.skip_trivia()?;
+		let leading = input[start..c.at].to_owned();
+		match c.peek() {
+			None if is_root => {
+				return Ok(Body {
+					items,
+					trailing: leading,
+				});
+			},
+			None => return Err(c.error("Unexpected end of input, expected '}'")),
+			Some(b'}') if !is_root => {
+				c.at += 1;
+				return Ok(Body {
+					items,
+					trailing: leading,
+				});
+			},
+			Some(b'}') => return Err(c.error("Unexpected '}' without opening block")),
+			Some(_) => items.push(parse_item(c, input, leading)?),
+		}
+	}
+}
+
+// Mirrors `Cursor::parse_field`, but keeps track of where each token starts and ends
+fn parse_item(c: &mut Cursor, input: &str, leading: String) -> Result<Item, TextParseError> {
+	let key_start = c.at;
+	let (name, key_end, type_span) = match c.peek() {
+		Some(q @ (b'"' | b'\'')) => {
+			let name = c.parse_quoted(q)?;
+			let key_end = c.at;
+			c.skip_inline_space();
+			(name, key_end, parse_type(c)?)
+		},
+		_ => {
+			let token = c.parse_bare()?;
+			let token_end = c.at;
+			c.skip_inline_space();
+			match c.peek() {
+				Some(b'=') => {
+					let (name, _) = token
+						.rsplit_once(':')
+						.ok_or_else(|| c.error(format!("Missing type for field {token}")))?;
+					let key_end = key_start + name.len();
+					(name.to_owned(), key_end, Some((key_end + 1, token_end)))
+				},
+				Some(q @ (b'"' | b'\'')) if token == "include" => {
+					let value_start = c.at;
+					let path = c.parse_quoted(q)?;
+					let value_end = c.at;
+					c.expect_value_end()?;
+					c.at = value_end;
+					// Includes have no type, the path is stored as string value
+					return Ok(Item {
+						leading,
+						name: INCLUDE_FIELD.to_owned(),
+						key: token,
+						kind: ItemKind::Value {
+							separator:  input[token_end..value_start].to_owned(),
+							ty:         String::new(),
+							assignment: String::new(),
+							raw:        input[value_start..value_end].to_owned(),
+							value:      BlkType::Str(blk_str(path)),
+						},
+					});
+				},
+				_ => (token, token_end, parse_type(c)?),
+			}
+		},
+	};
+	let key = input[key_start..key_end].to_owned();
+
+	let Some((type_start, type_end)) = type_span else {
+		c.skip_trivia()?;
+		if c.peek() != Some(b'{') {
+			return Err(c.error(format!("Expected '{{' or type after {name}")));
+		}
+		c.at += 1;
+		let open = input[key_end..c.at].to_owned();
+		let body = parse_body(c, input, false)?;
+		return Ok(Item {
+			leading,
+			name,
+			key,
+			kind: ItemKind::Block { open, body },
+		});
+	};
+
+	let ty = &input[type_start..type_end];
+	if !BlkType::is_valid_type(ty) {
+		return Err(c.error(format!("Unknown type {ty} for field {name}")));
+	}
+	c.expect(b'=')?;
+	c.skip_inline_space();
+	let value_start = c.at;
+	let value = c.parse_value(ty)?;
+	let value_end = c.at;
+	c.expect_value_end()?;
+	// Spaces after the value belong to the trivia of whatever follows
+	c.at = value_end;
+	Ok(Item {
+		leading,
+		name,
+		key,
+		kind: ItemKind::Value {
+			separator: input[key_end..type_start].to_owned(),
+			ty: ty.to_owned(),
+			assignment: input[type_end..value_start].to_owned(),
+			raw: input[value_start..value_end].to_owned(),
+			value,
+		},
+	})
+}
+
+// Parses an optional `:type` following a name, returning where the type is located
+fn parse_type(c: &mut Cursor) -> Result<Option<(usize, usize)>, TextParseError> {
+	if c.peek() != Some(b':') {
+		return Ok(None);
+	}
+	c.at += 1;
+	c.skip_inline_space();
+	let start = c.at;
+	c.parse_ident()?;
+	Ok(Some((start, c.at)))
+}
+
+fn split_path(path: &str) -> (&str, &str) {
+	path.rsplit_once('/').unwrap_or(("", path))
+}
+
+// Indentation of a field, if it is the first on its line
+fn line_indent(leading: &str) -> Option<&str> {
+	let (_, indent) = leading.rsplit_once('\n')?;
+	indent
+		.bytes()
+		.all(|b| matches!(b, b' ' | b'\t'))
+		.then_some(indent)
+}
+
+// Merges the trivia of a removed field with the trivia of whatever follows it
+fn join_trivia(removed: &str, next: &str, has_previous: bool) -> String {
+	let same_line = || next.trim_start_matches([' ', '\t', ';']);
+	match (removed.rfind('\n'), next.find('\n')) {
+		// The removed field had its own line, which is dropped including its line break
+		(Some(r), Some(n)) => format!("{}{}", &removed[..=r], &next[n + 1..]),
+		(Some(r), None) if same_line().is_empty() => format!("{}{next}", &removed[..r]),
+		(Some(_), None) => format!("{removed}{}", same_line()),
+		// The removed field shared its line with the previous one
+		(None, Some(_)) if has_previous => next.to_owned(),
+		(None, Some(n)) => format!("{}{}", removed.trim_end(), &next[n + 1..]),
+		(None, None) => format!("{removed}{}", same_line()),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::fs;
+
+	use crate::blk::{
+		blk_string::blk_str,
+		blk_structure::BlkField,
+		blk_type::BlkType,
+		plaintext_deserialize::{cst::BlkDocument, deserialize_blk},
+	};
+
+	const COMMENTED: &str = "// Header comment\n\
+		\n\
+		engine {\n\
+		\x20 power:r = 1500.0 // horsepower\n\
+		\x20 /* boost */ boost:b=yes\n\
+		\n\
+		\x20 \"fuel tank\" { capacity:i = 200; }\n\
+		}\n\
+		weapon { trigger:t = \"machine gun\" }\n\
+		include \"#/common.blk\"\n";
+
+	#[test]
+	fn round_trip() {
+		for text in [
+			fs::read_to_string("./samples/section_strict.blk").unwrap(),
+			fs::read_to_string("./samples/expected").unwrap(),
+			COMMENTED.to_owned(),
+		] {
+			let doc = BlkDocument::parse(&text).unwrap();
+			assert_eq!(doc.to_string(), text);
+			assert_eq!(doc.to_blk_field(), deserialize_blk(&text).unwrap());
+		}
+	}
+
+	#[test]
+	fn set_value() {
+		let mut doc = BlkDocument::parse(COMMENTED).unwrap();
+		doc.set_value("engine/power", BlkType::Float(1500.0))
+			.unwrap();
+		assert_eq!(doc.to_string(), COMMENTED);
+
+		doc.set_value("engine/power", BlkType::Float(1750.5))
+			.unwrap();
+		doc.set_value("engine/boost", BlkType::Bool(false)).unwrap();
+		doc.set_value("engine/fuel tank/capacity", BlkType::Long(300))
+			.unwrap();
+		assert_eq!(
+			doc.to_string(),
+			COMMENTED
+				.replace("1500.0", "1750.5")
+				.replace("boost:b=yes", "boost:b=no")
+				.replace("capacity:i = 200", "capacity:i64 = 300")
+		);
+		assert_eq!(
+			*doc.value("engine/fuel tank/capacity").unwrap(),
+			BlkType::Long(300)
+		);
+		doc.set_value("@include", BlkType::Str(blk_str("other.blk")))
+			.unwrap();
+		assert!(doc.to_string().ends_with("include \"other.blk\"\n"));
+		assert!(doc.set_value("@include", BlkType::Int(1)).is_err());
+		assert!(doc.set_value("engine", BlkType::Int(1)).is_err());
+		assert!(doc.set_value("engine/missing", BlkType::Int(1)).is_err());
+	}
+
+	#[test]
+	fn insert() {
+		let mut doc =
+			BlkDocument::parse(&fs::read_to_string("./samples/section_strict.blk").unwrap())
+				.unwrap();
+		doc.insert_field(
+			"alpha/gamma",
+			&BlkField::Value(blk_str("extra"), BlkType::Int(1)),
+		)
+		.unwrap();
+		doc.insert_field_at(
+			"",
+			1,
+			&BlkField::Struct(
+				blk_str("new block"),
+				vec![BlkField::Value(blk_str("x"), BlkType::Float(2.5))],
+			),
+		)
+		.unwrap();
+		let text = doc.to_string();
+		assert!(text.starts_with(
+			"vec4f:p4=1.25, 2.5, 5.0, 10.0\n\"new block\"{\n  x:r=2.5\n}\nint:i=42\n"
+		));
+		assert!(text.contains("1.25, 2.5, 5.0]]\n    extra:i=1\n  }"));
+		assert!(deserialize_blk(&text).is_ok());
+
+		let mut doc = BlkDocument::parse("empty {}\n").unwrap();
+		doc.insert_field("empty", &BlkField::Value(blk_str("a"), BlkType::Int(1)))
+			.unwrap();
+		assert_eq!(doc.to_string(), "empty {\n\ta:i = 1\n}\n");
+	}
+
+	#[test]
+	fn remove() {
+		let mut doc = BlkDocument::parse(COMMENTED).unwrap();
+		doc.remove("engine/fuel tank").unwrap();
+		doc.remove("weapon").unwrap();
+		doc.remove("engine/power").unwrap();
+		assert_eq!(
+			doc.to_string(),
+			"// Header comment\n\nengine {\n  /* boost */ boost:b=yes\n\n}\ninclude \"#/common.blk\"\n"
+		);
+		assert!(doc.remove("engine/power").is_err());
+
+		let mut doc = BlkDocument::parse("a:i=1; b:i=2; c:i=3").unwrap();
+		doc.remove("b").unwrap();
+		doc.remove("a").unwrap();
+		assert_eq!(doc.to_string(), "c:i=3");
+	}
+}
@@ -0,0 +1,491 @@
+use color_eyre::{
+	Report,
+	eyre::{bail, eyre},
+};
+use serde_json::{Map, Value};
+
+use crate::blk::{
+	blk_string::{BlkString, blk_str},
+	blk_structure::BlkField,
+	blk_type::BlkType,
+};
+
+/// Decides how JSON values are typed when there is no reference field of the same name
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct JsonInference {
+	/// Whole numbers become `r` instead of `i` (or `i64` when exceeding 32 bits)
+	pub integers_as_float: bool,
+	/// Arrays of 2 to 4 numbers become vectors such as `p3`, and 4 arrays of 3 numbers a matrix.
+	/// When disabled, every array is treated as duplicate keys
+	pub vectors:           bool,
+	/// Values no longer fitting the type of their reference are an error, instead of being inferred anew
+	pub strict_reference:  bool,
+}
+
+impl JsonInference {
+	pub const fn standard() -> Self {
+		Self {
+			integers_as_float: false,
+			vectors:           true,
+			strict_reference:  false,
+		}
+	}
+}
+
+impl BlkField {
+	/// Converts JSON (as produced by [`BlkField::as_serde_json`]) back into a BLK struct, named `root`
+	/// Types are taken from the field of the same name in `reference` where possible, and inferred according to `inference` otherwise.
+	/// Arrays created by [`BlkField::merge_fields`] are expanded back into duplicate keys,
+	/// restoring their original position when the field names still line up with the reference.
+	pub fn from_json(
+		value: &Value,
+		inference: JsonInference,
+		reference: Option<&BlkField>,
+	) -> Result<Self, Report> {
+		let Value::Object(map) = value else {
+			bail!("Expected a JSON object as root, found: {value}");
+		};
+		object_to_struct(blk_str("root"), map, reference, inference)
+	}
+}
+
+impl BlkField {
+	/// Reads the lossless JSON form written by [`BlkField::as_typed_json_streaming`]
+	pub fn from_typed_json(value: &Value) -> Result<Self, Report> {
+		typed_entries(blk_str("root"), value)
+	}
+}
+
+fn typed_entries(name: BlkString, value: &Value) -> Result<BlkField, Report> {
+	let entries = value
+		.as_array()
+		.ok_or_else(|| eyre!("Expected an array of fields for {name}, found: {value}"))?;
+	let mut fields = Vec::with_capacity(entries.len());
+	for entry in entries {
+		let Some((key, value)) = entry
+			.as_object()
+			.filter(|e| e.len() == 1)
+			.and_then(|e| e.iter().next())
+		else {
+			bail!("Expected an object with exactly one key in {name}, found: {entry}");
+		};
+
+		// Blocks are the only arrays of objects, values always carry their type
+		if value
+			.as_array()
+			.is_some_and(|e| e.iter().all(Value::is_object))
+		{
+			fields.push(typed_entries(blk_str(key.as_str()), value)?);
+			continue;
+		}
+		let (field_name, type_name) = key
+			.rsplit_once(':')
+			.ok_or_else(|| eyre!("Field {key} in {name} is missing its type"))?;
+		let typed = named_value(value, type_name)
+			.ok_or_else(|| eyre!("Cannot convert {value} of field {key} into type {type_name}"))?;
+		fields.push(BlkField::Value(blk_str(field_name), typed));
+	}
+	Ok(BlkField::Struct(name, fields))
+}
+
+fn object_to_struct(
+	name: BlkString,
+	map: &Map<String, Value>,
+	reference: Option<&BlkField>,
+	inference: JsonInference,
+) -> Result<BlkField, Report> {
+	let ref_fields: &[BlkField] = match reference {
+		Some(BlkField::Struct(_, fields)) => fields,
+		_ => &[],
+	};
+
+	let mut fields = Vec::with_capacity(map.len());
+	let mut expanded = false;
+	for (key, value) in map {
+		let key = blk_str(key.as_str());
+		let refs = ref_fields
+			.iter()
+			.filter(|e| e.get_name() == key)
+			.collect::<Vec<_>>();
+
+		match value {
+			// Either a single vector value, or duplicate keys merged into an array
+			Value::Array(elems)
+				if refs.len() > 1
+					|| single_value(value, refs.first().and_then(|e| e.value()), inference)
+						.is_none() =>
+			{
+				expanded = true;
+				for (i, elem) in elems.iter().enumerate() {
+					fields.push(field_from_json(
+						key.clone(),
+						elem,
+						refs.get(i).copied(),
+						inference,
+					)?);
+				}
+			},
+			_ => fields.push(field_from_json(
+				key,
+				value,
+				refs.first().copied(),
+				inference,
+			)?),
+		}
+	}
+
+	// Merging moves all duplicates to the first occurrence, which is undone when the names still line up with the reference
+	if expanded {
+		fields = restore_order(fields, ref_fields);
+	}
+	Ok(BlkField::Struct(name, fields))
+}
+
+fn field_from_json(
+	name: BlkString,
+	value: &Value,
+	reference: Option<&BlkField>,
+	inference: JsonInference,
+) -> Result<BlkField, Report> {
+	match value {
+		Value::Object(map) => object_to_struct(name, map, reference, inference),
+		_ => {
+			let value = single_value(value, reference.and_then(BlkField::value), inference)
+				.ok_or_else(|| eyre!("Cannot convert {value} of field {name} into a BLK value"))?;
+			Ok(BlkField::Value(name, value))
+		},
+	}
+}
+
+// Prefers the type of the reference, falling back to inference when the value no longer fits it
+fn single_value(
+	value: &Value,
+	reference: Option<&BlkType>,
+	inference: JsonInference,
+) -> Option<BlkType> {
+	match reference.map(|ty| typed_value(value, ty)) {
+		Some(Some(typed)) => Some(typed),
+		Some(None) if inference.strict_reference => None,
+		_ => inferred_value(value, inference),
+	}
+}
+
+fn typed_value(value: &Value, ty: &BlkType) -> Option<BlkType> {
+	named_value(value, ty.blk_type_name())
+}
+
+// Converts to the type of the given name, see [`BlkType::blk_type_name`]
+fn named_value(value: &Value, type_name: &str) -> Option<BlkType> {
+	Some(match type_name {
+		"t" => BlkType::Str(blk_str(value.as_str()?)),
+		"i" => BlkType::Int(value.as_i64()?.try_into().ok()?),
+		"ip2" => BlkType::Int2(array(value, as_i32)?),
+		"ip3" => BlkType::Int3(array(value, as_i32)?),
+		"ip4" => BlkType::Int4(Box::new(array(value, as_i32)?)),
+		"i64" => BlkType::Long(value.as_i64()?),
+		"r" => BlkType::Float(as_f32(value)?),
+		"p2" => BlkType::Float2(array(value, as_f32)?),
+		"p3" => BlkType::Float3(array(value, as_f32)?),
+		"p4" => BlkType::Float4(Box::new(array(value, as_f32)?)),
+		"m" => BlkType::Float12(Box::new(matrix(value)?)),
+		"b" => BlkType::Bool(value.as_bool()?),
+		"c" => {
+			let [r, g, b, a] = array(value, |e| e.as_u64()?.try_into().ok())?;
+			BlkType::Color { r, g, b, a }
+		},
+		_ => return None,
+	})
+}
+
+fn inferred_value(value: &Value, inference: JsonInference) -> Option<BlkType> {
+	Some(match value {
+		Value::String(s) => BlkType::Str(blk_str(s.as_str())),
+		Value::Bool(b) => BlkType::Bool(*b),
+		Value::Number(n) if n.is_f64() || inference.integers_as_float => {
+			BlkType::Float(n.as_f64()? as f32)
+		},
+		Value::Number(n) => {
+			let n = n.as_i64()?;
+			match i32::try_from(n) {
+				Ok(n) => BlkType::Int(n),
+				Err(_) => BlkType::Long(n),
+			}
+		},
+		Value::Array(_) if !inference.vectors => return None,
+		Value::Array(elems) if !inference.integers_as_float && elems.iter().all(|e| e.is_i64()) => {
+			match elems.len() {
+				2 => BlkType::Int2(array(value, as_i32)?),
+				3 => BlkType::Int3(array(value, as_i32)?),
+				4 => BlkType::Int4(Box::new(array(value, as_i32)?)),
+				_ => return None,
+			}
+		},
+		Value::Array(elems) if elems.iter().all(Value::is_number) => match elems.len() {
+			2 => BlkType::Float2(array(value, as_f32)?),
+			3 => BlkType::Float3(array(value, as_f32)?),
+			4 => BlkType::Float4(Box::new(array(value, as_f32)?)),
+			_ => return None,
+		},
+		Value::Array(_) => BlkType::Float12(Box::new(matrix(value)?)),
+		Value::Null | Value::Object(_) => return None,
+	})
+}
+
+fn as_i32(value: &Value) -> Option<i32> {
+	value.as_i64()?.try_into().ok()
+}
+
+// Also accepts the strings non-finite floats are written as, see [`BlkField::as_typed_json_streaming`]
+fn as_f32(value: &Value) -> Option<f32> {
+	match value.as_str() {
+		Some("inf") => Some(f32::INFINITY),
+		Some("-inf") => Some(f32::NEG_INFINITY),
+		Some("nan") => Some(f32::NAN),
+		Some(_) => None,
+		None => value.as_f64().map(|e| e as f32),
+	}
+}
+
+fn array<T: Copy + Default, const N: usize>(
+	value: &Value,
+	convert: impl Fn(&Value) -> Option<T>,
+) -> Option<[T; N]> {
+	let elems = value.as_array().filter(|e| e.len() == N)?;
+	let mut out = [T::default(); N];
+	for (dst, elem) in out.iter_mut().zip(elems) {
+		*dst = convert(elem)?;
+	}
+	Some(out)
+}
+
+// Matrices are written as 4 rows of 3 floats
+fn matrix(value: &Value) -> Option<[f32; 12]> {
+	let rows: [[f32; 3]; 4] = array(value, |row| array(row, as_f32))?;
+	Some(rows.as_flattened().try_into().expect("Infallible"))
+}
+
+fn restore_order(fields: Vec<BlkField>, reference: &[BlkField]) -> Vec<BlkField> {
+	let mut names = fields.iter().map(BlkField::get_name).collect::<Vec<_>>();
+	let mut ref_names = reference.iter().map(BlkField::get_name).collect::<Vec<_>>();
+	names.sort_unstable();
+	ref_names.sort_unstable();
+	if names != ref_names {
+		return fields;
+	}
+
+	let mut pending = fields.into_iter().map(Some).collect::<Vec<_>>();
+	reference
+		.iter()
+		.map(|r| {
+			let name = r.get_name();
+			pending
+				.iter_mut()
+				.find(|e| e.as_ref().is_some_and(|e| e.get_name() == name))
+				.and_then(Option::take)
+				.expect("Names were checked to line up")
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod test {
+	use crate::blk::{
+		blk_string::blk_str,
+		blk_structure::BlkField,
+		blk_type::BlkType,
+		make_strict_test,
+		plaintext_deserialize::{deserialize_blk, json::JsonInference},
+	};
+
+	#[test]
+	fn round_trip_with_reference() {
+		let reference = make_strict_test();
+		let mut merged = reference.clone();
+		merged.merge_fields().unwrap();
+		let json = serde_json::from_slice(&merged.as_serde_json().unwrap()).unwrap();
+		assert_eq!(
+			BlkField::from_json(&json, JsonInference::standard(), Some(&reference)).unwrap(),
+			reference
+		);
+	}
+
+	#[test]
+	fn restores_duplicates() {
+		let reference = BlkField::Struct(
+			blk_str("root"),
+			vec![
+				BlkField::Value(blk_str("a"), BlkType::Long(1)),
+				BlkField::Value(
+					blk_str("b"),
+					BlkType::Color {
+						r: 1,
+						g: 2,
+						b: 3,
+						a: 4,
+					},
+				),
+				BlkField::Value(blk_str("a"), BlkType::Long(2)),
+			],
+		);
+		let json = serde_json::json!({"a": [1, 5], "b": [1, 2, 3, 4]});
+		let BlkField::Struct(_, fields) =
+			BlkField::from_json(&json, JsonInference::standard(), Some(&reference)).unwrap()
+		else {
+			panic!("Root is always a struct")
+		};
+		assert_eq!(
+			fields,
+			[
+				BlkField::Value(blk_str("a"), BlkType::Long(1)),
+				BlkField::Value(
+					blk_str("b"),
+					BlkType::Color {
+						r: 1,
+						g: 2,
+						b: 3,
+						a: 4,
+					}
+				),
+				BlkField::Value(blk_str("a"), BlkType::Long(5)),
+			]
+		);
+	}
+
+	#[test]
+	fn inferred() {
+		let json = serde_json::json!({"int": 1, "float": 1.0, "vec": [1.0, 2, 3], "blocks": [{}, {"x": "y"}]});
+		let BlkField::Struct(_, fields) =
+			BlkField::from_json(&json, JsonInference::standard(), None).unwrap()
+		else {
+			panic!("Root is always a struct")
+		};
+		assert_eq!(
+			fields,
+			[
+				BlkField::Value(blk_str("int"), BlkType::Int(1)),
+				BlkField::Value(blk_str("float"), BlkType::Float(1.0)),
+				BlkField::Value(blk_str("vec"), BlkType::Float3([1.0, 2.0, 3.0])),
+				BlkField::Struct(blk_str("blocks"), vec![]),
+				BlkField::Struct(
+					blk_str("blocks"),
+					vec![BlkField::Value(blk_str("x"), BlkType::Str(blk_str("y")))]
+				),
+			]
+		);
+	}
+
+	#[test]
+	fn policy() {
+		let reference = BlkField::Struct(
+			blk_str("root"),
+			vec![BlkField::Value(blk_str("speed"), BlkType::Float(1.5))],
+		);
+		let json = serde_json::json!({"speed": 42, "count": 3, "pos": [1, 2]});
+
+		let standard =
+			BlkField::from_json(&json, JsonInference::standard(), Some(&reference)).unwrap();
+		assert_eq!(
+			standard,
+			BlkField::Struct(
+				blk_str("root"),
+				vec![
+					// Whole number stays a float, as the reference says so
+					BlkField::Value(blk_str("speed"), BlkType::Float(42.0)),
+					BlkField::Value(blk_str("count"), BlkType::Int(3)),
+					BlkField::Value(blk_str("pos"), BlkType::Int2([1, 2])),
+				]
+			)
+		);
+
+		let floats = JsonInference {
+			integers_as_float: true,
+			vectors: false,
+			..JsonInference::standard()
+		};
+		let BlkField::Struct(_, fields) = BlkField::from_json(&json, floats, None).unwrap() else {
+			panic!("Root is always a struct")
+		};
+		assert_eq!(
+			fields[1],
+			BlkField::Value(blk_str("count"), BlkType::Float(3.0))
+		);
+		assert_eq!(
+			fields[2],
+			BlkField::Value(blk_str("pos"), BlkType::Float(1.0))
+		);
+		assert_eq!(
+			fields[3],
+			BlkField::Value(blk_str("pos"), BlkType::Float(2.0))
+		);
+
+		let strict = JsonInference {
+			strict_reference: true,
+			..JsonInference::standard()
+		};
+		let json = serde_json::json!({"speed": "fast"});
+		assert!(BlkField::from_json(&json, strict, Some(&reference)).is_err());
+		assert!(BlkField::from_json(&json, JsonInference::standard(), Some(&reference)).is_ok());
+	}
+
+	#[test]
+	fn typed_round_trip() {
+		let mut blk = make_strict_test();
+		blk.insert_field(BlkField::Value(blk_str("vec4f"), BlkType::Long(i64::MAX)))
+			.unwrap();
+		blk.insert_field(BlkField::Value(
+			blk_str("key:with:colons"),
+			BlkType::Float(42.0),
+		))
+		.unwrap();
+		blk.insert_field(BlkField::Struct(blk_str("block:r"), vec![]))
+			.unwrap();
+		let json = serde_json::from_str(&blk.as_typed_json_string().unwrap()).unwrap();
+		assert_eq!(BlkField::from_typed_json(&json).unwrap(), blk);
+
+		// Merged fields are written as the duplicates they originally were
+		let mut merged = blk.clone();
+		merged.merge_fields().unwrap();
+		let json = serde_json::from_str(&merged.as_typed_json_string().unwrap()).unwrap();
+		let (BlkField::Struct(_, fields), BlkField::Struct(_, expected)) =
+			(BlkField::from_typed_json(&json).unwrap(), blk)
+		else {
+			panic!("Root is always a struct")
+		};
+		assert_eq!(fields.len(), expected.len());
+		assert_eq!(
+			fields[1],
+			BlkField::Value(blk_str("vec4f"), BlkType::Long(i64::MAX))
+		);
+	}
+
+	#[test]
+	fn typed_non_finite() {
+		let blk = deserialize_blk(
+			"a:r=inf\nb:p3=-inf, 1, 2\nc:r=NaN\nd:m=[[1, 0, 0] [0, 1, 0] [0, 0, inf] [1, 2, 3]]",
+		)
+		.unwrap();
+		let text = blk.as_typed_json_string().unwrap();
+		assert!(text.contains("\"-inf\""));
+		let parsed = BlkField::from_typed_json(&serde_json::from_str(&text).unwrap()).unwrap();
+		assert!(parsed.get::<f32>("c").unwrap().is_nan());
+		// NaN never equals itself, so everything else is compared separately
+		let without_nan = |mut blk: BlkField| {
+			blk.remove("c").unwrap();
+			blk
+		};
+		assert_eq!(without_nan(parsed), without_nan(blk));
+
+		let json = serde_json::json!([{"speed:r": "fast"}]);
+		assert!(BlkField::from_typed_json(&json).is_err());
+	}
+
+	#[test]
+	fn typed_errors() {
+		let json = serde_json::json!([{"speed": 1}]);
+		assert!(BlkField::from_typed_json(&json).is_err());
+		let json = serde_json::json!([{"speed:i": 1.5}]);
+		assert!(BlkField::from_typed_json(&json).is_err());
+		let json = serde_json::json!({"speed:i": 1});
+		assert!(BlkField::from_typed_json(&json).is_err());
+	}
+}
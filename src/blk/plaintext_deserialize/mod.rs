@@ -1,77 +1,593 @@
-use color_eyre::Report;
+use std::ops::Range;
 
-use crate::blk::blk_structure::BlkField;
+use crate::blk::{
+	blk_string::blk_str,
+	blk_structure::BlkField,
+	blk_type::BlkType,
+	error::TextParseError,
+	escape,
+	include::INCLUDE_FIELD,
+};
 
-struct Cursor {
-	at:    usize,
-	inner: Vec<char>,
-}
+/// Conversion of JSON back into BLK, see [`BlkField::from_json`]
+pub mod json;
 
-impl Iterator for Cursor {
-	type Item = char;
+/// Lossless syntax tree of BLK text, for editing files while keeping comments and formatting
+pub mod cst;
 
-	fn next(&mut self) -> Option<Self::Item> {
-		let ret = self.inner.get(self.at).map(|e| *e);
-		self.at += 1;
-		ret
-	}
-}
-
-#[allow(unused)]
-pub fn deserialize_blk(input: &str) -> Result<BlkField, Report> {
+/// Parses BLK text (as produced by the game or [`BlkField::as_blk_text`]) into its internal representation
+/// The returned field is always the root struct, named `root`
+pub fn deserialize_blk(input: &str) -> Result<BlkField, TextParseError> {
 	let mut c = Cursor {
-		at:    input.chars().count(),
-		inner: input.chars().collect(),
+		at:    0,
+		inner: input.as_bytes(),
 	};
 	let mut root = BlkField::new_root();
-	_deserialize_blk(&mut c, &mut root)
+	c.parse_block_body(&mut root, true)?;
+	Ok(root)
 }
 
-enum State {
-	Key,
-	Type,
-	ParsingValue,
+struct Cursor<'a> {
+	at:    usize,
+	inner: &'a [u8],
 }
 
-fn _deserialize_blk(input: &mut Cursor, _parent: &mut BlkField) -> Result<BlkField, Report> {
-	let mut typename = String::new();
-	let mut typ = String::new();
-	let mut state = State::Key;
-
-	while let Some(char) = input.next() {
-		match state {
-			State::Key => {
-				if char != ':' {
-					typename.push(char);
-				} else {
-					state = State::Type;
+struct IntLiteral<'a> {
+	token:     &'a str,
+	negative:  bool,
+	hex:       bool,
+	magnitude: u64,
+}
+
+impl<'a> Cursor<'a> {
+	fn peek(&self) -> Option<u8> {
+		self.inner.get(self.at).copied()
+	}
+
+	fn peek_at(&self, offset: usize) -> Option<u8> {
+		self.inner.get(self.at + offset).copied()
+	}
+
+	/// Error pointing at the current character
+	fn error(&self, msg: impl Into<String>) -> TextParseError {
+		let len = self
+			.inner
+			.get(self.at..)
+			.and_then(|rest| {
+				String::from_utf8_lossy(&rest[..rest.len().min(4)])
+					.chars()
+					.next()
+			})
+			.map_or(0, char::len_utf8);
+		self.error_at(self.at..self.at + len, msg)
+	}
+
+	/// Error covering `span`, without the spaces preceding a token
+	fn error_at(&self, mut span: Range<usize>, msg: impl Into<String>) -> TextParseError {
+		while span.start < span.end && matches!(self.inner.get(span.start), Some(b' ' | b'\t')) {
+			span.start += 1;
+		}
+		TextParseError::new(self.inner, span, msg.into())
+	}
+
+	/// Skips whitespace, comments and `;` separators
+	fn skip_trivia(&mut self) -> Result<(), TextParseError> {
+		while let Some(b) = self.peek() {
+			match b {
+				b' ' | b'\t' | b'\r' | b'\n' | b';' => self.at += 1,
+				b'/' if self.peek_at(1) == Some(b'/') => {
+					while let Some(b) = self.peek() {
+						if b == b'\n' {
+							break;
+						}
+						self.at += 1;
+					}
+				},
+				b'/' if self.peek_at(1) == Some(b'*') => {
+					let start = self.at;
+					self.at += 2;
+					loop {
+						match self.peek() {
+							Some(b'*') if self.peek_at(1) == Some(b'/') => {
+								self.at += 2;
+								break;
+							},
+							Some(_) => self.at += 1,
+							None => {
+								return Err(
+									self.error_at(start..start + 2, "Unterminated block comment")
+								);
+							},
+						}
+					}
+				},
+				_ => break,
+			}
+		}
+		Ok(())
+	}
+
+	/// Skips spaces and tabs, but not newlines as they terminate values
+	fn skip_inline_space(&mut self) {
+		while let Some(b' ' | b'\t') = self.peek() {
+			self.at += 1;
+		}
+	}
+
+	fn expect(&mut self, expected: u8) -> Result<(), TextParseError> {
+		self.skip_inline_space();
+		if self.peek() == Some(expected) {
+			self.at += 1;
+			Ok(())
+		} else {
+			Err(self.error(format!("Expected '{}'", expected as char)))
+		}
+	}
+
+	/// Values are terminated by a newline, `;`, a comment, or the closing brace of their block
+	fn expect_value_end(&mut self) -> Result<(), TextParseError> {
+		self.skip_inline_space();
+		match self.peek() {
+			None | Some(b'\r' | b'\n' | b';' | b'}') => Ok(()),
+			Some(b'/') if matches!(self.peek_at(1), Some(b'/' | b'*')) => Ok(()),
+			Some(b) => Err(self.error(format!("Unexpected '{}' after value", b as char))),
+		}
+	}
+
+	fn parse_block_body(
+		&mut self,
+		parent: &mut BlkField,
+		is_root: bool,
+	) -> Result<(), TextParseError> {
+		loop {
+			self.skip_trivia()?;
+			match self.peek() {
+				None if is_root => return Ok(()),
+				None => return Err(self.error("Unexpected end of input, expected '}'")),
+				Some(b'}') if !is_root => {
+					self.at += 1;
+					return Ok(());
+				},
+				Some(b'}') => return Err(self.error("Unexpected '}' without opening block")),
+				Some(_) => {
+					let field = self.parse_field()?;
+					parent
+						.insert_field(field)
+						.ok_or_else(|| self.error("Attempted to insert field into non-struct"))?;
+				},
+			}
+		}
+	}
+
+	fn parse_field(&mut self) -> Result<BlkField, TextParseError> {
+		let (name, type_name) = match self.peek() {
+			Some(q @ (b'"' | b'\'')) => {
+				let name = self.parse_quoted(q)?;
+				self.skip_inline_space();
+				match self.peek() {
+					Some(b':') => {
+						self.at += 1;
+						self.skip_inline_space();
+						(name, Some(self.parse_ident()?))
+					},
+					_ => (name, None),
+				}
+			},
+			_ => {
+				let token = self.parse_bare()?;
+				self.skip_inline_space();
+				match self.peek() {
+					// Bare keys may themselves contain colons, such as `override:key:i=0`
+					Some(b'=') => {
+						let (name, ty) = token
+							.rsplit_once(':')
+							.ok_or_else(|| self.error(format!("Missing type for field {token}")))?;
+						(name.to_owned(), Some(ty.to_owned()))
+					},
+					Some(b':') => {
+						self.at += 1;
+						self.skip_inline_space();
+						(token, Some(self.parse_ident()?))
+					},
+					Some(q @ (b'"' | b'\'')) if token == "include" => {
+						let path = self.parse_quoted(q)?;
+						self.expect_value_end()?;
+						return Ok(BlkField::Value(
+							blk_str(INCLUDE_FIELD),
+							BlkType::Str(blk_str(path)),
+						));
+					},
+					_ => (token, None),
+				}
+			},
+		};
+
+		self.skip_inline_space();
+		match type_name {
+			None => {
+				self.skip_trivia()?;
+				if self.peek() != Some(b'{') {
+					return Err(self.error(format!("Expected '{{' or type after {name}")));
 				}
+				self.at += 1;
+				let mut block = BlkField::new_struct(blk_str(name));
+				self.parse_block_body(&mut block, false)?;
+				Ok(block)
 			},
-			State::Type => {
-				if char.is_ascii_alphanumeric() {
-					typ.push(char);
-				} else if char == '=' {
-					state = State::ParsingValue;
+			Some(ty) => {
+				if !BlkType::is_valid_type(&ty) {
+					return Err(self.error(format!("Unknown type {ty} for field {name}")));
 				}
+				self.expect(b'=')?;
+				self.skip_inline_space();
+				let value = self.parse_value(&ty)?;
+				self.expect_value_end()?;
+				Ok(BlkField::Value(blk_str(name), value))
 			},
-			State::ParsingValue => {},
 		}
 	}
 
-	todo!()
+	fn parse_bare(&mut self) -> Result<String, TextParseError> {
+		let start = self.at;
+		while let Some(b) = self.peek() {
+			match b {
+				b' ' | b'\t' | b'\r' | b'\n' | b'=' | b'{' | b'}' | b'"' | b'\'' | b';' => break,
+				_ => self.at += 1,
+			}
+		}
+		if start == self.at {
+			return Err(self.error("Expected name"));
+		}
+		Ok(String::from_utf8_lossy(&self.inner[start..self.at]).into_owned())
+	}
+
+	fn parse_ident(&mut self) -> Result<String, TextParseError> {
+		let start = self.at;
+		while let Some(b) = self.peek() {
+			if b.is_ascii_alphanumeric() {
+				self.at += 1;
+			} else {
+				break;
+			}
+		}
+		if start == self.at {
+			return Err(self.error("Expected type name"));
+		}
+		Ok(String::from_utf8_lossy(&self.inner[start..self.at]).into_owned())
+	}
+
+	fn parse_quoted(&mut self, quote: u8) -> Result<String, TextParseError> {
+		let start = self.at;
+		self.at += 1;
+		let mut content = Vec::new();
+		while let Some(b) = self.peek() {
+			self.at += 1;
+			if b == quote {
+				return Ok(String::from_utf8_lossy(&content).into_owned());
+			}
+			if b == escape::ESCAPE as u8 {
+				if let Some(escaped) = self.peek() {
+					content.push(escape::unescape(escaped));
+					self.at += 1;
+				}
+			} else {
+				content.push(b);
+			}
+		}
+		Err(self.error_at(start..start + 1, "Unterminated string"))
+	}
+
+	/// Yields the raw token of a scalar value, such as a number or boolean
+	fn parse_scalar(&mut self) -> Result<&'a str, TextParseError> {
+		self.skip_inline_space();
+		let start = self.at;
+		while let Some(b) = self.peek() {
+			if b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'+') {
+				self.at += 1;
+			} else {
+				break;
+			}
+		}
+		if start == self.at {
+			return Err(self.error("Expected value"));
+		}
+		std::str::from_utf8(&self.inner[start..self.at]).map_err(|_| self.error("Invalid UTF-8"))
+	}
+
+	fn parse_int(&mut self) -> Result<i64, TextParseError> {
+		let start = self.at;
+		let int = self.parse_int_literal()?;
+		let v = if int.negative {
+			0_i64.checked_sub_unsigned(int.magnitude)
+		} else if int.hex {
+			// Hex literals such as 0xFFFFFFFFFFFFFFFF are valid representations of negative integers
+			Some(int.magnitude as i64)
+		} else {
+			i64::try_from(int.magnitude).ok()
+		};
+		v.ok_or_else(|| {
+			self.error_at(
+				start..self.at,
+				format!("Integer {} out of range for 64 bits", int.token),
+			)
+		})
+	}
+
+	fn parse_i32(&mut self) -> Result<i32, TextParseError> {
+		let start = self.at;
+		let int = self.parse_int_literal()?;
+		let v = if int.negative {
+			u32::try_from(int.magnitude)
+				.ok()
+				.and_then(|v| 0_i32.checked_sub_unsigned(v))
+		} else {
+			i32::try_from(int.magnitude).ok().or_else(|| {
+				// Hex literals such as 0xFFFFFFFF are valid representations of negative 32 bit integers
+				int.hex
+					.then(|| u32::try_from(int.magnitude).ok().map(|v| v as i32))
+					.flatten()
+			})
+		};
+		v.ok_or_else(|| {
+			self.error_at(
+				start..self.at,
+				format!("Integer {} out of range for 32 bits", int.token),
+			)
+		})
+	}
+
+	// Sign and magnitude of a decimal or hex integer, leaving the range check to the caller
+	fn parse_int_literal(&mut self) -> Result<IntLiteral<'a>, TextParseError> {
+		let start = self.at;
+		let token = self.parse_scalar()?;
+		let (negative, digits) = match token.strip_prefix('-') {
+			Some(rest) => (true, rest),
+			None => (false, token.strip_prefix('+').unwrap_or(token)),
+		};
+		let hex = digits
+			.strip_prefix("0x")
+			.or_else(|| digits.strip_prefix("0X"));
+		let magnitude = match hex {
+			Some(hex) => u64::from_str_radix(hex, 16),
+			None => digits.parse::<u64>(),
+		};
+		match magnitude {
+			// Rejects signs following the prefix, which from_str_radix would accept
+			Ok(magnitude) if !digits.contains(['-', '+']) => Ok(IntLiteral {
+				token,
+				negative,
+				hex: hex.is_some(),
+				magnitude,
+			}),
+			_ => Err(self.error_at(start..self.at, format!("Invalid integer {token}"))),
+		}
+	}
+
+	fn parse_f32(&mut self) -> Result<f32, TextParseError> {
+		let start = self.at;
+		let token = self.parse_scalar()?;
+		token
+			.parse::<f32>()
+			.map_err(|_| self.error_at(start..self.at, format!("Invalid float {token}")))
+	}
+
+	fn parse_u8(&mut self) -> Result<u8, TextParseError> {
+		let start = self.at;
+		let v = self.parse_int()?;
+		u8::try_from(v).map_err(|_| {
+			self.error_at(
+				start..self.at,
+				format!("Color component {v} out of range 0..=255"),
+			)
+		})
+	}
+
+	fn parse_list<T, const N: usize>(
+		&mut self,
+		f: impl Fn(&mut Self) -> Result<T, TextParseError>,
+	) -> Result<[T; N], TextParseError>
+	where
+		T: Copy + Default, {
+		let mut out = [T::default(); N];
+		for (i, elem) in out.iter_mut().enumerate() {
+			if i != 0 {
+				self.expect(b',')?;
+			}
+			*elem = f(self)?;
+		}
+		Ok(out)
+	}
+
+	fn parse_value(&mut self, ty: &str) -> Result<BlkType, TextParseError> {
+		Ok(match ty {
+			"t" => match self.peek() {
+				Some(q @ (b'"' | b'\'')) => BlkType::Str(blk_str(self.parse_quoted(q)?)),
+				_ => return Err(self.error("Expected quoted string")),
+			},
+			"i" => BlkType::Int(self.parse_i32()?),
+			"i64" => BlkType::Long(self.parse_int()?),
+			"ip2" => BlkType::Int2(self.parse_list(Self::parse_i32)?),
+			"ip3" => BlkType::Int3(self.parse_list(Self::parse_i32)?),
+			"ip4" => BlkType::Int4(Box::new(self.parse_list(Self::parse_i32)?)),
+			"r" => BlkType::Float(self.parse_f32()?),
+			"p2" => BlkType::Float2(self.parse_list(Self::parse_f32)?),
+			"p3" => BlkType::Float3(self.parse_list(Self::parse_f32)?),
+			"p4" => BlkType::Float4(Box::new(self.parse_list(Self::parse_f32)?)),
+			"m" => {
+				let mut out = [0.0; 12];
+				self.expect(b'[')?;
+				for row in out.chunks_exact_mut(3) {
+					self.skip_trivia()?;
+					self.expect(b'[')?;
+					row.copy_from_slice(&self.parse_list::<f32, 3>(Self::parse_f32)?);
+					self.expect(b']')?;
+				}
+				self.skip_trivia()?;
+				self.expect(b']')?;
+				BlkType::Float12(Box::new(out))
+			},
+			"b" => {
+				let start = self.at;
+				let token = self.parse_scalar()?;
+				BlkType::Bool(match token {
+					"yes" | "true" | "on" | "1" => true,
+					"no" | "false" | "off" | "0" => false,
+					_ => {
+						return Err(
+							self.error_at(start..self.at, format!("Invalid boolean {token}"))
+						);
+					},
+				})
+			},
+			"c" => {
+				let mut channels = [255_u8; 4];
+				for (i, channel) in channels.iter_mut().enumerate() {
+					if i != 0 {
+						self.skip_inline_space();
+						// Alpha is optional and defaults to opaque
+						if i == 3 && self.peek() != Some(b',') {
+							break;
+						}
+						self.expect(b',')?;
+					}
+					*channel = self.parse_u8()?;
+				}
+				let [r, g, b, a] = channels;
+				// The internal representation follows the binary byte order (BGRA), while text is written as RGBA
+				BlkType::Color { r: b, g, b: r, a }
+			},
+			_ => return Err(self.error(format!("Unknown type {ty}"))),
+		})
+	}
 }
 
-#[allow(unused)]
 #[cfg(test)]
 mod test {
 	use std::fs;
 
-	use crate::blk::{make_strict_test, plaintext_deserialize::deserialize_blk};
+	use crate::blk::{
+		blk_string::blk_str,
+		blk_structure::BlkField,
+		blk_type::{BlkFormatting, BlkType},
+		make_strict_test,
+		plaintext_deserialize::deserialize_blk,
+	};
 
-	// #[test]
+	#[test]
 	fn test_simple() {
 		let to_parse = fs::read_to_string("./samples/section_strict.blk").unwrap();
 
 		assert_eq!(deserialize_blk(&to_parse).unwrap(), make_strict_test())
 	}
+
+	#[test]
+	fn quoted_and_hex() {
+		let to_parse = fs::read_to_string("./samples/expected").unwrap();
+
+		assert_eq!(deserialize_blk(&to_parse).unwrap(), make_strict_test())
+	}
+
+	#[test]
+	fn text_round_trip() {
+		let text = make_strict_test()
+			.as_blk_text(BlkFormatting::standard())
+			.unwrap();
+		assert_eq!(deserialize_blk(&text).unwrap(), make_strict_test())
+	}
+
+	#[test]
+	fn comments_and_quotes() {
+		let parsed = deserialize_blk(
+			"// leading comment\n\"with space\":t='say \"hi\"' /* trailing */\nflag:b=no; other:b=true\n",
+		)
+		.unwrap();
+		let mut expected = BlkField::new_root();
+		expected
+			.insert_field(BlkField::Value(
+				blk_str("with space"),
+				BlkType::Str(blk_str("say \"hi\"")),
+			))
+			.unwrap();
+		expected
+			.insert_field(BlkField::Value(blk_str("flag"), BlkType::Bool(false)))
+			.unwrap();
+		expected
+			.insert_field(BlkField::Value(blk_str("other"), BlkType::Bool(true)))
+			.unwrap();
+		assert_eq!(parsed, expected);
+	}
+
+	#[test]
+	fn colon_in_key() {
+		let parsed = deserialize_blk("override:value:i=42").unwrap();
+		assert_eq!(
+			*parsed.pointer("override:value").unwrap().value().unwrap(),
+			BlkType::Int(42)
+		);
+	}
+
+	#[test]
+	fn bad_float() {
+		let err = deserialize_blk("block {\n  speed:r = 1,5\n}").unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Unexpected ',' after value at line 2, column 14"
+		);
+		assert_eq!(err.span(), 21..22);
+		assert_eq!(
+			err.snippet(),
+			"  |\n2 |   speed:r = 1,5\n  |              ^"
+		);
+	}
+
+	#[test]
+	fn error_spans() {
+		let err = deserialize_blk("a:i=1\n\tspeed:r = 1.5.5 // comment\n").unwrap_err();
+		assert_eq!((err.line(), err.column(), err.span()), (2, 12, 17..22));
+		assert_eq!(err.message(), "Invalid float 1.5.5");
+		assert_eq!(
+			err.snippet(),
+			"  |\n2 | \tspeed:r = 1.5.5 // comment\n  | \t          ^^^^^"
+		);
+
+		let err = deserialize_blk("name:t = \"unterminated").unwrap_err();
+		assert_eq!((err.column(), err.span()), (10, 9..10));
+		let err = deserialize_blk("block {").unwrap_err();
+		assert_eq!((err.line(), err.column(), err.span()), (1, 8, 7..7));
+	}
+
+	#[test]
+	fn integer_ranges() {
+		let value = |text: &str| {
+			deserialize_blk(text)
+				.map(|blk| blk.get_value("v").unwrap().clone())
+				.map_err(|e| e.message().to_owned())
+		};
+		assert_eq!(
+			value("v:i64=-9223372036854775808"),
+			Ok(BlkType::Long(i64::MIN))
+		);
+		assert_eq!(
+			value("v:i64=-0x8000000000000000"),
+			Ok(BlkType::Long(i64::MIN))
+		);
+		assert_eq!(value("v:i64=0xFFFFFFFFFFFFFFFF"), Ok(BlkType::Long(-1)));
+		assert_eq!(
+			value("v:i64=-0xFFFFFFFFFFFFFFFF"),
+			Err("Integer -0xFFFFFFFFFFFFFFFF out of range for 64 bits".to_owned())
+		);
+		assert!(value("v:i64=9223372036854775808").is_err());
+		assert!(value("v:i64=-0x-1").is_err());
+
+		assert_eq!(value("v:i=-2147483648"), Ok(BlkType::Int(i32::MIN)));
+		assert_eq!(value("v:i=0xFFFFFFFF"), Ok(BlkType::Int(-1)));
+		assert!(value("v:i=4294967295").is_err());
+		assert!(value("v:i=-0xFFFFFFFF").is_err());
+		assert!(value("v:i=2147483648").is_err());
+	}
+
+	#[test]
+	fn unclosed_block() {
+		assert!(deserialize_blk("block {\n  speed:r = 1.5\n").is_err());
+	}
 }
@@ -3,7 +3,9 @@ use color_eyre::{Report, eyre::bail};
 use crate::blk::{
 	blk_string::BlkString,
 	blk_structure::BlkField,
-	blk_type::{BlkFormatting, BlkType},
+	blk_type::{BlkFormatting, BlkType, KeyQuoting},
+	escape,
+	include::INCLUDE_FIELD,
 };
 
 impl BlkField {
@@ -12,7 +14,6 @@ impl BlkField {
 		self.inner_as_blk_text(&mut 0, true, format)
 	}
 
-	// TODO: Make this generic with a configuration file
 	// Internal fn that actually formats
 	fn inner_as_blk_text(
 		&self,
@@ -21,31 +22,50 @@ impl BlkField {
 		format: BlkFormatting,
 	) -> Result<String, Report> {
 		match self {
-			BlkField::Value(name, value) => Ok(format!(
-				"{name}:{value}",
-				name = escape_key(name),
-				value = escape_value(value, format)
-			)),
+			BlkField::Value(name, BlkType::Str(path)) if name.as_str() == INCLUDE_FIELD => {
+				Ok(format!("include {}", escape::quote(path)))
+			},
+			BlkField::Value(name, value) => {
+				let line = format!(
+					"{name}:{value}",
+					name = escape_key(name, format.key_quoting),
+					value = escape_value(value, format)
+				);
+				let indent = format.indent.repeat(indent_level.saturating_sub(1));
+				match (value, format.matrix_line_width) {
+					(BlkType::Float12(m), Some(width))
+						if line_width(&indent) + line.len() > width =>
+					{
+						Ok(matrix_lines(name, m, &indent, format))
+					},
+					_ => Ok(line),
+				}
+			},
 			BlkField::Struct(name, fields) => {
-				let indent = "\t".repeat(*indent_level);
+				let indent = format.indent.repeat(*indent_level);
 				*indent_level += 1;
-				let children = fields
-					.iter()
-					.map(|x| {
-						Ok(format!(
-							"{indent}{}",
-							x.inner_as_blk_text(indent_level, false, format)?
-						))
-					})
-					.collect::<Result<Vec<_>, Report>>()?
-					.join("\n");
+				let mut children = String::new();
+				for (i, field) in fields.iter().enumerate() {
+					if i != 0 {
+						children.push('\n');
+						if field.is_block() || fields[i - 1].is_block() {
+							children.push_str(&"\n".repeat(format.blank_lines_around_blocks));
+						}
+					}
+					children.push_str(&indent);
+					children.push_str(&field.inner_as_blk_text(indent_level, false, format)?);
+				}
 				*indent_level -= 1;
 
-				let indent_closing = "\t".repeat(indent_level.saturating_sub(1));
+				let indent_closing = format.indent.repeat(indent_level.saturating_sub(1));
+				let name = escape_key(name, format.key_quoting);
+				let brace = if format.space_before_brace { " {" } else { "{" };
 				Ok(if is_root {
-					format!("{children}")
+					children
+				} else if children.is_empty() {
+					format!("{name}{brace}\n{indent_closing}}}")
 				} else {
-					format!("{name} {{\n{children}\n{indent_closing}}}")
+					format!("{name}{brace}\n{children}\n{indent_closing}}}")
 				})
 			},
 			BlkField::Merged(..) => {
@@ -55,37 +75,82 @@ impl BlkField {
 			},
 		}
 	}
+
+	fn is_block(&self) -> bool {
+		matches!(self, BlkField::Struct(..))
+	}
 }
 
-fn escape_key(field: &BlkString) -> String {
-	if field.contains(' ') {
-		format!("\"{field}\"")
+// Tabs are assumed to be 4 columns wide
+fn line_width(indent: &str) -> usize {
+	indent.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+}
+
+fn matrix_lines(name: &BlkString, m: &[f32; 12], indent: &str, format: BlkFormatting) -> String {
+	let assignment = if format.assignment_spacing {
+		" = "
+	} else {
+		"="
+	};
+	let mut out = format!("{}:m{assignment}[", escape_key(name, format.key_quoting));
+	for row in m.as_chunks::<3>().0 {
+		let values = value_text(
+			&BlkType::Float3(*row),
+			BlkFormatting {
+				force_float_point: true,
+				..format
+			},
+		);
+		out.push_str(&format!("\n{indent}{}[{values}]", format.indent));
+	}
+	out.push_str(&format!("\n{indent}]"));
+	out
+}
+
+/// Quotes keys according to `quoting`, see [`escape::quote`]
+pub(crate) fn escape_key(field: &BlkString, quoting: KeyQuoting) -> String {
+	if quoting == KeyQuoting::Always || escape::key_needs_quotes(field) {
+		escape::quote(field)
 	} else {
 		field.to_string()
 	}
 }
 
-// Slowpath for escaping, it's a rare case
 fn escape_value(value: &BlkType, format: BlkFormatting) -> String {
+	let assignment = if format.assignment_spacing {
+		" = "
+	} else {
+		"="
+	};
+	format!(
+		"{ty}{assignment}{value}",
+		ty = value.blk_type_name(),
+		value = value_text(value, format)
+	)
+}
+
+/// Formats only the value, quoting strings the same way as [`escape_value`]
+pub(crate) fn value_text(value: &BlkType, format: BlkFormatting) -> String {
 	match value {
-		BlkType::Str(s) => {
-			if s.contains('\"') {
-				format!("{ty} = '{s}'", ty = value.blk_type_name())
-			} else {
-				value.to_string_with(format)
-			}
+		BlkType::Str(s) => escape::quote(s),
+		_ => {
+			let mut buf = Vec::new();
+			let _ = value.fmt_value_with(&mut buf, format); // Infallible
+			String::from_utf8(buf).unwrap()
 		},
-		_ => value.to_string_with(format),
 	}
 }
 
 #[cfg(test)]
 mod test {
+	use std::fs;
+
 	use crate::blk::{
 		blk_string::blk_str,
 		blk_structure::BlkField,
-		blk_type::{BlkFormatting, BlkType},
+		blk_type::{BlkFormatting, BlkType, Indent, KeyQuoting},
 		make_strict_test,
+		plaintext_deserialize::deserialize_blk,
 	};
 
 	#[test]
@@ -111,4 +176,77 @@ mod test {
 			r#""totally not escaped":t = 'this is totally not escaped " '"#
 		);
 	}
+
+	#[test]
+	fn strict_style() {
+		let format = BlkFormatting {
+			yesno_booleans: true,
+			assignment_spacing: false,
+			indent: Indent::Spaces(2),
+			space_before_brace: false,
+			blank_lines_around_blocks: 1,
+			force_float_point: true,
+			..BlkFormatting::standard()
+		};
+		assert_eq!(
+			make_strict_test().as_blk_text(format).unwrap(),
+			fs::read_to_string("./samples/section_strict.blk").unwrap()
+		);
+	}
+
+	#[test]
+	fn configured() {
+		let root = BlkField::Struct(
+			blk_str("root"),
+			vec![
+				BlkField::Value(blk_str("a=b"), BlkType::Float(1.0 / 3.0)),
+				BlkField::Value(blk_str("plain"), BlkType::Float2([2.0, -0.00001])),
+				BlkField::Struct(
+					blk_str("block"),
+					vec![BlkField::Value(
+						blk_str("tm"),
+						BlkType::Float12(Box::new([
+							1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.5, 2.5, 3.5,
+						])),
+					)],
+				),
+				BlkField::Struct(blk_str("empty"), vec![]),
+			],
+		);
+		let format = BlkFormatting {
+			float_precision: Some(3),
+			key_quoting: KeyQuoting::Always,
+			matrix_line_width: Some(40),
+			..BlkFormatting::standard()
+		};
+		let text = root.as_blk_text(format).unwrap();
+		assert_eq!(
+			text,
+			"\"a=b\":r = 0.333\n\"plain\":p2 = 2, 0\n\"block\" {\n\t\"tm\":m = [\n\t\t[1.0, 0.0, 0.0]\n\t\t[0.0, 1.0, 0.0]\n\t\t[0.0, 0.0, 1.0]\n\t\t[1.5, 2.5, 3.5]\n\t]\n}\n\"empty\" {\n}"
+		);
+		assert!(deserialize_blk(&text).is_ok());
+
+		// Keys are only quoted when necessary by default
+		let text = root.as_blk_text(BlkFormatting::standard()).unwrap();
+		assert!(text.starts_with("\"a=b\":r = 0.33333334\nplain:p2 = 2, -0.00001\nblock {"));
+		assert_eq!(deserialize_blk(&text).unwrap(), root);
+
+		// Nested blocks read back the same with any indentation
+		let nested = BlkField::Struct(
+			blk_str("root"),
+			vec![BlkField::Struct(
+				blk_str("outer"),
+				vec![root.clone(), BlkField::Struct(blk_str("inner"), vec![])],
+			)],
+		);
+		for indent in [Indent::Tabs, Indent::Spaces(2), Indent::Spaces(4)] {
+			let format = BlkFormatting {
+				indent,
+				..BlkFormatting::standard()
+			};
+			let text = nested.as_blk_text(format).unwrap();
+			assert!(text.contains(&format!("\n{}tm:m", indent.repeat(3))));
+			assert_eq!(deserialize_blk(&text).unwrap(), nested);
+		}
+	}
 }
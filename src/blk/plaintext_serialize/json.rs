@@ -2,10 +2,15 @@ use std::{io::Write, mem};
 
 use color_eyre::Report;
 use foldhash::HashMapExt;
+use serde::{
+	Serialize,
+	Serializer,
+	ser::{SerializeMap, SerializeSeq},
+};
 use serde_json::ser::{Formatter, PrettyFormatter};
 use smallvec::{SmallVec, smallvec};
 
-use crate::blk::{blk_string::BlkString, blk_structure::BlkField};
+use crate::blk::{blk_string::BlkString, blk_structure::BlkField, blk_type::BlkType};
 
 impl BlkField {
 	/// Merges duplicate keys in struct fields into the Merged array variant
@@ -147,6 +152,127 @@ impl BlkField {
 	}
 }
 
+/// Lossless JSON form, see [`BlkField::as_typed_json_streaming`]
+struct TypedEntries<'a>(&'a [BlkField]);
+
+pub(crate) struct TypedEntry<'a>(pub(crate) &'a BlkField);
+
+pub(crate) struct TypedValue<'a>(pub(crate) &'a BlkType);
+
+// JSON has no representation of non-finite numbers, so they are written as strings
+struct TypedFloats<'a>(&'a [f32]);
+
+impl Serialize for TypedEntries<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut seq = serializer.serialize_seq(None)?;
+		for field in self.0 {
+			match field {
+				// Merged fields are written as the duplicates they originally were
+				BlkField::Merged(_, fields) => {
+					for field in fields {
+						seq.serialize_element(&TypedEntry(field))?;
+					}
+				},
+				_ => seq.serialize_element(&TypedEntry(field))?,
+			}
+		}
+		seq.end()
+	}
+}
+
+impl Serialize for TypedEntry<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(1))?;
+		match self.0 {
+			BlkField::Value(k, v) => {
+				map.serialize_entry(&format!("{k}:{}", v.blk_type_name()), &TypedValue(v))?
+			},
+			BlkField::Struct(k, fields) | BlkField::Merged(k, fields) => {
+				map.serialize_entry(k.as_str(), &TypedEntries(fields))?
+			},
+		}
+		map.end()
+	}
+}
+
+impl Serialize for TypedValue<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self.0 {
+			BlkType::Str(v) => serializer.serialize_str(v.as_str()),
+			BlkType::Int(v) => serializer.serialize_i32(*v),
+			BlkType::Int2(v) => v.serialize(serializer),
+			BlkType::Int3(v) => v.serialize(serializer),
+			BlkType::Int4(v) => v.serialize(serializer),
+			BlkType::Long(v) => serializer.serialize_i64(*v),
+			BlkType::Float(v) => serialize_typed_f32(*v, serializer),
+			BlkType::Float2(v) => TypedFloats(v).serialize(serializer),
+			BlkType::Float3(v) => TypedFloats(v).serialize(serializer),
+			BlkType::Float4(v) => TypedFloats(&v[..]).serialize(serializer),
+			BlkType::Float12(v) => {
+				let mut seq = serializer.serialize_seq(Some(4))?;
+				for row in v.chunks_exact(3) {
+					seq.serialize_element(&TypedFloats(row))?;
+				}
+				seq.end()
+			},
+			BlkType::Bool(v) => serializer.serialize_bool(*v),
+			BlkType::Color { r, g, b, a } => [r, g, b, a].serialize(serializer),
+		}
+	}
+}
+
+impl Serialize for TypedFloats<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+		for v in self.0 {
+			seq.serialize_element(&TypedFloat(*v))?;
+		}
+		seq.end()
+	}
+}
+
+struct TypedFloat(f32);
+
+impl Serialize for TypedFloat {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serialize_typed_f32(self.0, serializer)
+	}
+}
+
+fn serialize_typed_f32<S: Serializer>(v: f32, serializer: S) -> Result<S::Ok, S::Error> {
+	match v {
+		f32::INFINITY => serializer.serialize_str("inf"),
+		f32::NEG_INFINITY => serializer.serialize_str("-inf"),
+		_ if v.is_nan() => serializer.serialize_str("nan"),
+		_ => serializer.serialize_f32(v),
+	}
+}
+
+impl BlkField {
+	/// Writes a lossless JSON form, that can be read back using [`BlkField::from_typed_json`]
+	///
+	/// Every struct is an array of single-key objects, keeping field order and duplicate keys.
+	/// Values are keyed `name:type` like in BLK text, for example `{"speed:r": 42.0}`,
+	/// while blocks are keyed by their name and contain their fields, for example `{"weapon": [...]}`.
+	/// Non-finite floats are written as the strings `"inf"`, `"-inf"` and `"nan"`.
+	pub fn as_typed_json_streaming(&self, w: &mut impl Write) -> Result<(), Report> {
+		match self {
+			BlkField::Struct(_, fields) => {
+				serde_json::to_writer_pretty(&mut *w, &TypedEntries(fields))?
+			},
+			_ => serde_json::to_writer_pretty(&mut *w, &TypedEntries(std::slice::from_ref(self)))?,
+		}
+		w.flush()?;
+		Ok(())
+	}
+
+	pub fn as_typed_json_string(&self) -> Result<String, Report> {
+		let mut res = vec![];
+		self.as_typed_json_streaming(&mut res)?;
+		Ok(String::from_utf8(res)?)
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use std::fs;
@@ -3,3 +3,5 @@
 mod blockfile;
 /// Formats BLK to Json
 pub mod json;
+
+pub(crate) use blockfile::{escape_key, value_text};
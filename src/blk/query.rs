@@ -0,0 +1,496 @@
+//! A query is a `/` separated list of steps, each matching the children of the fields selected so far:
+//! - `name` selects children called `name`, `*` and `?` act as wildcards as in `gun*`
+//! - `**` selects the current fields and all their descendants, `//` is shorthand for `/**/`
+//! - `[n]` keeps the n-th (0-based) of the matching children of each parent, `[*]` keeps all of them
+//! - `[key]` keeps fields that have a child `key`, which may be a path itself
+//! - `[key=value]` compares the value at `key`, also supporting `!=`, `<`, `<=`, `>` and `>=`.
+//!   `.` refers to the field itself, as in `//speed[.>100]`.
+//!   Strings are quoted, numbers compare across integer and float types, booleans are `yes`/`no` or `true`/`false`
+//!
+//! ```
+//! # use wt_blk::blk::plaintext_deserialize::deserialize_blk;
+//! let blk = deserialize_blk("weapon { trigger:t=\"machine gun\"; bullet { speed:r=800 } }").unwrap();
+//! let found = blk.query("//weapon[trigger=\"machine gun\"]/bullet/speed").unwrap();
+//! assert_eq!(found[0].path, "weapon/bullet/speed");
+//! ```
+
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+	str::FromStr,
+};
+
+use crate::blk::{
+	blk_string::BlkString,
+	blk_structure::BlkField,
+	blk_type::BlkType,
+	error::QueryError,
+};
+
+/// Parsed query, reusable across trees
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+	steps: Vec<Step>,
+}
+
+/// Field selected by a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryMatch<'a> {
+	/// Path from the queried field, indexed where keys repeat so it can be passed to [`BlkField::get_field`]
+	pub path:  String,
+	pub field: &'a BlkField,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+	Descendants,
+	Children {
+		pattern: String,
+		filters: Vec<Filter>,
+	},
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+	Index(usize),
+	Exists(String),
+	Compare {
+		key:     String,
+		op:      Op,
+		literal: Literal,
+	},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+	Str(String),
+	Num(f64),
+	Bool(bool),
+}
+
+impl Query {
+	pub fn parse(query: &str) -> Result<Self, QueryError> {
+		let bytes = query.as_bytes();
+		let mut steps = vec![];
+		let mut at = 0;
+		if query.starts_with("//") {
+			steps.push(Step::Descendants);
+			at = 2;
+		} else if query.starts_with('/') {
+			at = 1;
+		}
+
+		while at < bytes.len() {
+			let start = at;
+			while at < bytes.len() && !matches!(bytes[at], b'/' | b'[') {
+				at += 1;
+			}
+			let pattern = &query[start..at];
+			if pattern.is_empty() {
+				return Err(QueryError::EmptyStep(start));
+			}
+
+			let mut filters = vec![];
+			while bytes.get(at) == Some(&b'[') {
+				let end = closing_bracket(query, at)?;
+				filters.extend(parse_filter(&query[at + 1..end])?);
+				at = end + 1;
+			}
+
+			if pattern == "**" && filters.is_empty() {
+				steps.push(Step::Descendants);
+			} else {
+				steps.push(Step::Children {
+					pattern: pattern.to_owned(),
+					filters,
+				});
+			}
+
+			match bytes.get(at) {
+				None => {},
+				Some(b'/') => {
+					if bytes.get(at + 1) == Some(&b'/') {
+						steps.push(Step::Descendants);
+						at += 1;
+					}
+					at += 1;
+					if at == bytes.len() {
+						return Err(QueryError::EmptyStep(at));
+					}
+				},
+				Some(_) => return Err(QueryError::ExpectedSeparator(at)),
+			}
+		}
+		Ok(Self { steps })
+	}
+
+	/// Runs the query from `root`, returning matches in document order
+	pub fn run<'a>(&self, root: &'a BlkField) -> Vec<QueryMatch<'a>> {
+		let mut current = vec![QueryMatch {
+			path:  String::new(),
+			field: root,
+		}];
+		for step in &self.steps {
+			let mut next = vec![];
+			for found in &current {
+				match step {
+					Step::Descendants => descendants(found, &mut next),
+					Step::Children { pattern, filters } => {
+						children(found, pattern, filters, &mut next)
+					},
+				}
+			}
+			// Descendant steps can reach a field through several paths
+			let mut seen = HashSet::new();
+			next.retain(|e| seen.insert(e.field as *const BlkField));
+			current = next;
+		}
+		current
+	}
+}
+
+impl FromStr for Query {
+	type Err = QueryError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::parse(s)
+	}
+}
+
+impl BlkField {
+	/// Parses and runs a query, see the [`query`](crate::blk::query) module for its syntax
+	pub fn query(&self, query: &str) -> Result<Vec<QueryMatch<'_>>, QueryError> {
+		Ok(Query::parse(query)?.run(self))
+	}
+}
+
+// Position of the `]` closing the bracket at `open`, ignoring brackets in quotes
+fn closing_bracket(query: &str, open: usize) -> Result<usize, QueryError> {
+	let mut quote = None;
+	for (i, c) in query[open + 1..].char_indices() {
+		match (c, quote) {
+			('"' | '\'', None) => quote = Some(c),
+			(c, Some(q)) if c == q => quote = None,
+			(']', None) => return Ok(open + 1 + i),
+			_ => {},
+		}
+	}
+	Err(QueryError::UnclosedBracket(open))
+}
+
+fn parse_filter(filter: &str) -> Result<Option<Filter>, QueryError> {
+	let filter = filter.trim();
+	if filter == "*" {
+		return Ok(None);
+	}
+	if let Ok(index) = filter.parse() {
+		return Ok(Some(Filter::Index(index)));
+	}
+	let invalid = || QueryError::InvalidPredicate(filter.to_owned());
+
+	// The operator is the first one outside of a key, which cannot contain operator characters
+	let Some(op_start) = filter.find(['=', '!', '<', '>']) else {
+		return Ok(Some(Filter::Exists(filter.to_owned())));
+	};
+	let (key, rest) = filter.split_at(op_start);
+	let (op, literal) = [
+		("!=", Op::Ne),
+		("<=", Op::Le),
+		(">=", Op::Ge),
+		("=", Op::Eq),
+		("<", Op::Lt),
+		(">", Op::Gt),
+	]
+	.into_iter()
+	.find_map(|(token, op)| Some((op, rest.strip_prefix(token)?)))
+	.ok_or_else(invalid)?;
+
+	let key = key.trim();
+	let literal = literal.trim();
+	if key.is_empty() || literal.is_empty() {
+		return Err(invalid());
+	}
+	let literal = match literal.as_bytes()[0] {
+		q @ (b'"' | b'\'') => Literal::Str(
+			literal
+				.strip_prefix(q as char)
+				.and_then(|e| e.strip_suffix(q as char))
+				.ok_or_else(invalid)?
+				.to_owned(),
+		),
+		_ => match literal {
+			"yes" | "true" => Literal::Bool(true),
+			"no" | "false" => Literal::Bool(false),
+			other => other
+				.parse()
+				.map_or_else(|_| Literal::Str(other.to_owned()), Literal::Num),
+		},
+	};
+	Ok(Some(Filter::Compare {
+		key: key.to_owned(),
+		op,
+		literal,
+	}))
+}
+
+fn descendants<'a>(found: &QueryMatch<'a>, out: &mut Vec<QueryMatch<'a>>) {
+	out.push(found.clone());
+	for (segment, field) in child_segments(found.field) {
+		descendants(
+			&QueryMatch {
+				path: join(&found.path, &segment),
+				field,
+			},
+			out,
+		);
+	}
+}
+
+fn children<'a>(
+	found: &QueryMatch<'a>,
+	pattern: &str,
+	filters: &[Filter],
+	out: &mut Vec<QueryMatch<'a>>,
+) {
+	let mut candidates: Vec<_> = child_segments(found.field)
+		.filter(|(_, field)| glob(pattern, field.get_name().as_str()))
+		.collect();
+	for filter in filters {
+		match filter {
+			Filter::Index(i) => {
+				candidates = candidates.into_iter().nth(*i).into_iter().collect();
+			},
+			filter => candidates.retain(|(_, field)| filter.matches(field)),
+		}
+	}
+	out.extend(candidates.into_iter().map(|(segment, field)| QueryMatch {
+		path: join(&found.path, &segment),
+		field,
+	}));
+}
+
+// Children with the path segment selecting them, which is indexed for repeated names
+fn child_segments(field: &BlkField) -> impl Iterator<Item = (String, &BlkField)> {
+	let fields = field.fields();
+	let mut counts: HashMap<BlkString, usize> = HashMap::new();
+	for child in fields {
+		*counts.entry(child.get_name()).or_default() += 1;
+	}
+	let mut seen: HashMap<BlkString, usize> = HashMap::new();
+	fields.iter().map(move |child| {
+		let name = child.get_name();
+		if counts[&name] == 1 {
+			return (name.to_string(), child);
+		}
+		let index = seen.entry(name.clone()).or_default();
+		*index += 1;
+		(format!("{name}[{}]", *index - 1), child)
+	})
+}
+
+fn join(path: &str, segment: &str) -> String {
+	if path.is_empty() {
+		segment.to_owned()
+	} else {
+		format!("{path}/{segment}")
+	}
+}
+
+// Glob match supporting `*` and `?`
+fn glob(pattern: &str, name: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let name: Vec<char> = name.chars().collect();
+	let (mut p, mut n) = (0, 0);
+	// Position of the last `*` and the name position it was tried at
+	let mut backtrack = None;
+	while n < name.len() {
+		match pattern.get(p) {
+			Some('*') => {
+				backtrack = Some((p, n));
+				p += 1;
+			},
+			Some(&c) if c == '?' || c == name[n] => {
+				p += 1;
+				n += 1;
+			},
+			_ => match backtrack {
+				Some((star, tried)) => {
+					backtrack = Some((star, tried + 1));
+					p = star + 1;
+					n = tried + 1;
+				},
+				None => return false,
+			},
+		}
+	}
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl Filter {
+	fn matches(&self, field: &BlkField) -> bool {
+		let target = |key: &str| {
+			if key == "." {
+				Some(field)
+			} else {
+				field.get_field(key).ok()
+			}
+		};
+		match self {
+			Filter::Index(_) => true,
+			Filter::Exists(key) => target(key).is_some(),
+			Filter::Compare { key, op, literal } => match target(key) {
+				Some(BlkField::Value(_, value)) => {
+					literal.compare(value).is_some_and(|ord| match op {
+						Op::Eq => ord == Ordering::Equal,
+						Op::Ne => ord != Ordering::Equal,
+						Op::Lt => ord == Ordering::Less,
+						Op::Le => ord != Ordering::Greater,
+						Op::Gt => ord == Ordering::Greater,
+						Op::Ge => ord != Ordering::Less,
+					})
+				},
+				_ => false,
+			},
+		}
+	}
+}
+
+impl Literal {
+	// Ordering of the value relative to the literal, none if they cannot be compared
+	fn compare(&self, value: &BlkType) -> Option<Ordering> {
+		match (value, self) {
+			(BlkType::Str(s), Literal::Str(literal)) => Some(s.as_str().cmp(literal.as_str())),
+			(BlkType::Int(v), Literal::Num(literal)) => f64::from(*v).partial_cmp(literal),
+			(BlkType::Long(v), Literal::Num(literal)) => (*v as f64).partial_cmp(literal),
+			(BlkType::Float(v), Literal::Num(literal)) => f64::from(*v).partial_cmp(literal),
+			(BlkType::Bool(v), Literal::Bool(literal)) => Some(v.cmp(literal)),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::blk::{
+		blk_type::BlkType,
+		error::QueryError,
+		plaintext_deserialize::deserialize_blk,
+		query::Query,
+	};
+
+	fn sample() -> crate::blk::blk_structure::BlkField {
+		deserialize_blk(
+			"\
+			weapon_presets { preset { name:t=\"default\" } }\n\
+			Weapon { trigger:t=\"machine gun\"; bullet { speed:r=800 } bullet { speed:r=850 } }\n\
+			Weapon { trigger:t=\"cannon\"; bullet { speed:r=1000 } }\n\
+			turret { Weapon { trigger:t=\"machine gun\"; bullet { speed:i=900 } } }\n\
+			gun_sight:b=yes",
+		)
+		.unwrap()
+	}
+
+	fn paths(query: &str) -> Vec<String> {
+		let blk = sample();
+		blk.query(query)
+			.unwrap()
+			.into_iter()
+			.map(|e| e.path)
+			.collect()
+	}
+
+	#[test]
+	fn wildcards() {
+		assert_eq!(paths("gun*"), ["gun_sight"]);
+		assert_eq!(
+			paths("Weapon[1]/*"),
+			["Weapon[1]/trigger", "Weapon[1]/bullet"]
+		);
+		assert_eq!(
+			paths("Weapon/bullet[*]/speed"),
+			[
+				"Weapon[0]/bullet[0]/speed",
+				"Weapon[0]/bullet[1]/speed",
+				"Weapon[1]/bullet/speed"
+			]
+		);
+		assert_eq!(paths("W?apon[0]/bullet[1]"), ["Weapon[0]/bullet[1]"]);
+	}
+
+	#[test]
+	fn recursive() {
+		assert_eq!(
+			paths("**/bullet/speed"),
+			[
+				"Weapon[0]/bullet[0]/speed",
+				"Weapon[0]/bullet[1]/speed",
+				"Weapon[1]/bullet/speed",
+				"turret/Weapon/bullet/speed"
+			]
+		);
+		assert_eq!(paths("//speed"), paths("**/bullet/speed"));
+		assert_eq!(paths("turret//speed"), ["turret/Weapon/bullet/speed"]);
+		assert_eq!(paths("**/**/name"), ["weapon_presets/preset/name"]);
+	}
+
+	#[test]
+	fn predicates() {
+		assert_eq!(
+			paths("//Weapon[trigger=\"machine gun\"]"),
+			["Weapon[0]", "turret/Weapon"]
+		);
+		assert_eq!(paths("//Weapon[trigger!='machine gun']"), ["Weapon[1]"]);
+		assert_eq!(
+			paths("//bullet[speed>=850]/speed"),
+			[
+				"Weapon[0]/bullet[1]/speed",
+				"Weapon[1]/bullet/speed",
+				"turret/Weapon/bullet/speed"
+			]
+		);
+		assert_eq!(paths("//speed[. < 850]"), ["Weapon[0]/bullet[0]/speed"]);
+		assert_eq!(paths("//Weapon[bullet/speed=900]"), ["turret/Weapon"]);
+		assert_eq!(paths("*[preset]"), ["weapon_presets"]);
+		assert_eq!(paths("gun_sight[.=yes]"), ["gun_sight"]);
+		// Indices count per parent, after the preceding predicates
+		assert_eq!(
+			paths("//Weapon[trigger=\"machine gun\"][0]"),
+			["Weapon[0]", "turret/Weapon"]
+		);
+		assert_eq!(paths("//Weapon[trigger=\"cannon\"][0]"), ["Weapon[1]"]);
+
+		// Paths can be fed back into the path based accessors
+		let blk = sample();
+		for found in blk.query("//speed").unwrap() {
+			assert_eq!(blk.get_field(&found.path).unwrap(), found.field);
+		}
+		assert_eq!(
+			blk.query("Weapon[1]/bullet/speed").unwrap()[0]
+				.field
+				.value(),
+			Some(&BlkType::Float(1000.0))
+		);
+	}
+
+	#[test]
+	fn invalid() {
+		assert_eq!(Query::parse("a//"), Err(QueryError::EmptyStep(3)));
+		assert_eq!(Query::parse("a/"), Err(QueryError::EmptyStep(2)));
+		assert_eq!(Query::parse("a[b"), Err(QueryError::UnclosedBracket(1)));
+		assert_eq!(Query::parse("a[0]b"), Err(QueryError::ExpectedSeparator(4)));
+		assert!(matches!(
+			Query::parse("a[b=]"),
+			Err(QueryError::InvalidPredicate(_))
+		));
+	}
+}
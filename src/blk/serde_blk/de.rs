@@ -0,0 +1,500 @@
+use std::iter::once;
+
+use indexmap::IndexMap;
+use serde::{
+	Deserialize,
+	de::{
+		DeserializeSeed,
+		Deserializer,
+		EnumAccess,
+		Error,
+		IntoDeserializer,
+		MapAccess,
+		VariantAccess,
+		Visitor,
+		value::{BorrowedStrDeserializer, MapDeserializer, SeqDeserializer},
+	},
+	forward_to_deserialize_any,
+};
+
+use crate::blk::{blk_structure::BlkField, blk_type::BlkType, error::DeserializeError};
+
+/// Deserializes any `T` directly from a BLK field, usually the root struct
+///
+/// The mapping between BLK and serde's data model is as follows:
+/// - Structs are maps, keys occurring multiple times are sequences (`Vec<T>`), as are [`BlkField::Merged`] fields
+/// - A key occurring once can still be read as a sequence with a single element
+/// - Vector types such as `p3` are sequences, fitting tuples and arrays, matrices are 4 rows of 3 floats
+/// - Colors are a map of `r`, `g`, `b` and `a`, with channels ordered as they are written in BLK text
+/// - Enums are either strings naming a unit variant, or a block with a single field named after the variant
+pub fn from_blk<'de, T: Deserialize<'de>>(blk: &'de BlkField) -> Result<T, DeserializeError> {
+	T::deserialize(blk)
+}
+
+impl<'de> Deserializer<'de> for &'de BlkField {
+	type Error = DeserializeError;
+
+	forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+		bytes byte_buf unit unit_struct map struct identifier ignored_any
+	}
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		match self {
+			BlkField::Value(_, value) => visit_value(value, visitor),
+			BlkField::Struct(_, fields) => visitor.visit_map(FieldMap::new(fields)),
+			BlkField::Merged(_, fields) => {
+				SeqDeserializer::new(fields.iter()).deserialize_any(visitor)
+			},
+		}
+	}
+
+	fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		match self {
+			BlkField::Value(_, value) if is_sequence(value) => visit_value(value, visitor),
+			BlkField::Merged(..) => self.deserialize_any(visitor),
+			// Keys that may repeat, but occurred once
+			_ => SeqDeserializer::new(once(self)).deserialize_any(visitor),
+		}
+	}
+
+	fn deserialize_tuple<V: Visitor<'de>>(
+		self,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		self.deserialize_seq(visitor)
+	}
+
+	fn deserialize_tuple_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		self.deserialize_seq(visitor)
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		visitor.visit_some(self)
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		visitor.visit_newtype_struct(self)
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		match self {
+			BlkField::Value(_, BlkType::Str(s)) => {
+				visitor.visit_enum(BorrowedStrDeserializer::new(s.as_str()))
+			},
+			BlkField::Struct(_, fields) if fields.len() == 1 => {
+				visitor.visit_enum(Variant(&fields[0]))
+			},
+			_ => Err(DeserializeError::custom(
+				"expected a string or a block with a single field for an enum",
+			)),
+		}
+	}
+}
+
+impl<'de> IntoDeserializer<'de, DeserializeError> for &'de BlkField {
+	type Deserializer = Self;
+
+	fn into_deserializer(self) -> Self::Deserializer {
+		self
+	}
+}
+
+fn field_name(field: &BlkField) -> &str {
+	match field {
+		BlkField::Value(name, _) | BlkField::Struct(name, _) | BlkField::Merged(name, _) => {
+			name.as_str()
+		},
+	}
+}
+
+fn is_sequence(value: &BlkType) -> bool {
+	matches!(
+		value,
+		BlkType::Int2(_)
+			| BlkType::Int3(_)
+			| BlkType::Int4(_)
+			| BlkType::Float2(_)
+			| BlkType::Float3(_)
+			| BlkType::Float4(_)
+			| BlkType::Float12(_)
+	)
+}
+
+fn visit_value<'de, V: Visitor<'de>>(
+	value: &'de BlkType,
+	visitor: V,
+) -> Result<V::Value, DeserializeError> {
+	match value {
+		BlkType::Str(v) => visitor.visit_borrowed_str(v.as_str()),
+		BlkType::Int(v) => visitor.visit_i32(*v),
+		BlkType::Int2(v) => SeqDeserializer::new(v.iter().copied()).deserialize_any(visitor),
+		BlkType::Int3(v) => SeqDeserializer::new(v.iter().copied()).deserialize_any(visitor),
+		BlkType::Int4(v) => SeqDeserializer::new(v.iter().copied()).deserialize_any(visitor),
+		BlkType::Long(v) => visitor.visit_i64(*v),
+		BlkType::Float(v) => visitor.visit_f32(*v),
+		BlkType::Float2(v) => SeqDeserializer::new(v.iter().copied()).deserialize_any(visitor),
+		BlkType::Float3(v) => SeqDeserializer::new(v.iter().copied()).deserialize_any(visitor),
+		BlkType::Float4(v) => SeqDeserializer::new(v.iter().copied()).deserialize_any(visitor),
+		BlkType::Float12(v) => {
+			SeqDeserializer::new(v.chunks_exact(3).map(Row)).deserialize_any(visitor)
+		},
+		BlkType::Bool(v) => visitor.visit_bool(*v),
+		// Red and blue are swapped in memory, see `BlkType::fmt_with`
+		BlkType::Color { r, g, b, a } => {
+			MapDeserializer::new([("r", *b), ("g", *g), ("b", *r), ("a", *a)].into_iter())
+				.deserialize_any(visitor)
+		},
+	}
+}
+
+/// One row of a matrix
+struct Row<'de>(&'de [f32]);
+
+impl<'de> IntoDeserializer<'de, DeserializeError> for Row<'de> {
+	type Deserializer =
+		SeqDeserializer<std::iter::Copied<std::slice::Iter<'de, f32>>, DeserializeError>;
+
+	fn into_deserializer(self) -> Self::Deserializer {
+		SeqDeserializer::new(self.0.iter().copied())
+	}
+}
+
+/// Struct fields grouped by their name, in order of first occurrence
+struct FieldMap<'de> {
+	groups:  indexmap::map::IntoIter<&'de str, Vec<&'de BlkField>>,
+	current: Option<(&'de str, Vec<&'de BlkField>)>,
+}
+
+impl<'de> FieldMap<'de> {
+	fn new(fields: &'de [BlkField]) -> Self {
+		let mut groups: IndexMap<&str, Vec<&BlkField>> = IndexMap::with_capacity(fields.len());
+		for field in fields {
+			let group = groups.entry(field_name(field)).or_default();
+			match field {
+				BlkField::Merged(_, merged) => group.extend(merged),
+				_ => group.push(field),
+			}
+		}
+		Self {
+			groups:  groups.into_iter(),
+			current: None,
+		}
+	}
+}
+
+impl<'de> MapAccess<'de> for FieldMap<'de> {
+	type Error = DeserializeError;
+
+	fn next_key_seed<K: DeserializeSeed<'de>>(
+		&mut self,
+		seed: K,
+	) -> Result<Option<K::Value>, Self::Error> {
+		let Some((key, fields)) = self.groups.next() else {
+			return Ok(None);
+		};
+		self.current = Some((key, fields));
+		seed.deserialize(BorrowedStrDeserializer::new(key))
+			.map(Some)
+	}
+
+	fn next_value_seed<V: DeserializeSeed<'de>>(
+		&mut self,
+		seed: V,
+	) -> Result<V::Value, Self::Error> {
+		let (key, fields) = self
+			.current
+			.take()
+			.ok_or_else(|| DeserializeError::custom("value requested before its key"))?;
+		match fields.as_slice() {
+			[single] => seed.deserialize(*single),
+			_ => seed.deserialize(Repeated(fields)),
+		}
+		.map_err(|e| e.in_field(key))
+	}
+
+	fn size_hint(&self) -> Option<usize> {
+		Some(self.groups.len())
+	}
+}
+
+/// Key occurring multiple times in one struct, which is a sequence unless something else is requested
+/// In that case, the first occurrence is used
+struct Repeated<'de>(Vec<&'de BlkField>);
+
+macro_rules! forward_to_first {
+	($($method:ident)*) => {
+		$(
+			fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+				self.0[0].$method(visitor)
+			}
+		)*
+	};
+}
+
+impl<'de> Deserializer<'de> for Repeated<'de> {
+	type Error = DeserializeError;
+
+	forward_to_first! {
+		deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+		deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32 deserialize_f64
+		deserialize_char deserialize_str deserialize_string deserialize_map deserialize_identifier
+	}
+
+	forward_to_deserialize_any! {
+		i128 u128 bytes byte_buf unit unit_struct seq tuple tuple_struct ignored_any
+	}
+
+	fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		SeqDeserializer::new(self.0.into_iter()).deserialize_any(visitor)
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self,
+		name: &'static str,
+		fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		self.0[0].deserialize_struct(name, fields, visitor)
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self,
+		name: &'static str,
+		variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		self.0[0].deserialize_enum(name, variants, visitor)
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(
+		self,
+		name: &'static str,
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		self.0[0].deserialize_newtype_struct(name, visitor)
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		visitor.visit_some(self)
+	}
+}
+
+/// Block with a single field, whose name is the variant
+struct Variant<'de>(&'de BlkField);
+
+impl<'de> EnumAccess<'de> for Variant<'de> {
+	type Error = DeserializeError;
+	type Variant = Self;
+
+	fn variant_seed<V: DeserializeSeed<'de>>(
+		self,
+		seed: V,
+	) -> Result<(V::Value, Self::Variant), Self::Error> {
+		let variant = seed.deserialize(BorrowedStrDeserializer::new(field_name(self.0)))?;
+		Ok((variant, self))
+	}
+}
+
+impl<'de> VariantAccess<'de> for Variant<'de> {
+	type Error = DeserializeError;
+
+	fn unit_variant(self) -> Result<(), Self::Error> {
+		Ok(())
+	}
+
+	fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+		self,
+		seed: T,
+	) -> Result<T::Value, Self::Error> {
+		seed.deserialize(self.0)
+	}
+
+	fn tuple_variant<V: Visitor<'de>>(
+		self,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		self.0.deserialize_seq(visitor)
+	}
+
+	fn struct_variant<V: Visitor<'de>>(
+		self,
+		_fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		self.0.deserialize_any(visitor)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use serde::Deserialize;
+
+	use crate::blk::{
+		error::DeserializeError,
+		make_strict_test,
+		plaintext_deserialize::deserialize_blk,
+		serde_blk::from_blk,
+	};
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	struct Root {
+		vec4f: [f32; 4],
+		int:   i32,
+		long:  u64,
+		alpha: Alpha,
+		beta:  Beta,
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	struct Alpha {
+		str:   String,
+		bool:  bool,
+		color: Color,
+		gamma: Gamma,
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	struct Color {
+		r: u8,
+		g: u8,
+		b: u8,
+		a: u8,
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	struct Gamma {
+		vec2i:     (i32, i32),
+		vec2f:     [f32; 2],
+		transform: [[f32; 3]; 4],
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	struct Beta {
+		float: f64,
+		vec2i: Vec<i32>,
+		vec3f: [f32; 3],
+	}
+
+	#[test]
+	fn strict() {
+		let blk = make_strict_test();
+		let root: Root = from_blk(&blk).unwrap();
+		assert_eq!(root.vec4f, [1.25, 2.5, 5.0, 10.0]);
+		assert_eq!(root.long, 64);
+		assert_eq!(
+			root.alpha.color,
+			Color {
+				r: 1,
+				g: 2,
+				b: 3,
+				a: 4,
+			}
+		);
+		assert_eq!(root.alpha.gamma.transform[3], [1.25, 2.5, 5.0]);
+		assert_eq!(root.beta.vec2i, [1, 2]);
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	struct Weapons<'a> {
+		#[serde(borrow)]
+		weapon:  Vec<Weapon<'a>>,
+		#[serde(borrow)]
+		single:  Vec<Weapon<'a>>,
+		#[serde(borrow)]
+		first:   Weapon<'a>,
+		kind:    Kind,
+		mount:   Mount,
+		missing: Option<i32>,
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	struct Weapon<'a> {
+		trigger: &'a str,
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	enum Kind {
+		Cannon,
+	}
+
+	#[derive(Debug, Deserialize, PartialEq)]
+	enum Mount {
+		Turret { yaw: f32 },
+	}
+
+	#[test]
+	fn repeated_keys() {
+		let blk = deserialize_blk(
+			r#"
+			weapon { trigger:t = "machine gun" }
+			weapon { trigger:t = "cannon" }
+			single { trigger:t = "bombs" }
+			first { trigger:t = "a" }
+			first { trigger:t = "b" }
+			kind:t = "Cannon"
+			mount { Turret { yaw:r = 90 } }
+			"#,
+		)
+		.unwrap();
+		let weapons: Weapons = from_blk(&blk).unwrap();
+		assert_eq!(
+			weapons,
+			Weapons {
+				weapon:  vec![
+					Weapon {
+						trigger: "machine gun",
+					},
+					Weapon { trigger: "cannon" },
+				],
+				single:  vec![Weapon { trigger: "bombs" }],
+				first:   Weapon { trigger: "a" },
+				kind:    Kind::Cannon,
+				mount:   Mount::Turret { yaw: 90.0 },
+				missing: None,
+			}
+		);
+
+		// Merging does not change the result
+		let mut merged = blk.clone();
+		merged.merge_fields().unwrap();
+		assert_eq!(from_blk::<Weapons>(&merged).unwrap(), weapons);
+	}
+
+	#[derive(Debug, Deserialize)]
+	#[allow(dead_code)]
+	struct Outer {
+		alpha: Inner,
+	}
+
+	#[derive(Debug, Deserialize)]
+	#[allow(dead_code)]
+	struct Inner {
+		str: String,
+	}
+
+	#[test]
+	fn error_path() {
+		let blk = deserialize_blk("alpha { str:i = 5 }").unwrap();
+		let Err(DeserializeError::InField { path, .. }) = from_blk::<Outer>(&blk) else {
+			panic!("Expected an error nested in a field")
+		};
+		assert_eq!(path, "alpha/str");
+	}
+}
@@ -0,0 +1,7 @@
+/// Deserializing Rust types directly from [`BlkField`](crate::blk::blk_structure::BlkField)
+pub mod de;
+/// Serializing Rust types into [`BlkField`](crate::blk::blk_structure::BlkField)
+pub mod ser;
+
+pub use de::from_blk;
+pub use ser::{Color, Repeated, to_blk};
@@ -0,0 +1,741 @@
+use serde::{
+	Deserialize,
+	Serialize,
+	Serializer,
+	ser::{
+		Error,
+		Impossible,
+		SerializeMap,
+		SerializeSeq,
+		SerializeStruct,
+		SerializeStructVariant,
+		SerializeTuple,
+		SerializeTupleStruct,
+		SerializeTupleVariant,
+	},
+};
+
+use crate::blk::{
+	blk_string::{BlkString, blk_str},
+	blk_structure::BlkField,
+	blk_type::BlkType,
+	error::SerializeError,
+};
+
+// Newtype names recognized by the serializer, other serializers treat them like regular newtypes
+const REPEATED_TOKEN: &str = "$wt_blk::Repeated";
+const COLOR_TOKEN: &str = "$wt_blk::Color";
+
+/// Serializes any `T` into a root struct, `T` has to be a struct or map
+///
+/// Types map onto BLK as follows, mirroring [`crate::blk::from_blk`]:
+/// - Structs and maps become blocks, `None` and `()` fields are omitted
+/// - `i8` to `i32`, `u8` and `u16` become `i`, while `i64`, `u32` and `u64` become `i64`
+/// - `f32` and `f64` become `r`, strings and chars `t`, and `bool` `b`
+/// - Sequences of 2 to 4 numbers become vectors (`ip2`..`ip4` when all are integers, `p2`..`p4` otherwise), and 4 sequences of 3 numbers a matrix
+/// - Any other sequence is written as repeated keys, which can be forced using [`Repeated`]
+/// - Colors are written using [`Color`]
+/// - Unit enum variants become strings, other variants a block with a single field named after the variant
+pub fn to_blk<T: Serialize + ?Sized>(value: &T) -> Result<BlkField, SerializeError> {
+	match value.serialize(NodeSerializer)? {
+		Node::Block(fields) => Ok(BlkField::Struct(blk_str("root"), fields)),
+		_ => Err(SerializeError::RootNotStruct),
+	}
+}
+
+/// Always serializes its elements as repeated keys, even when they would form a vector like `p3`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Repeated<T>(pub Vec<T>);
+
+impl<T: Serialize> Serialize for Repeated<T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_newtype_struct(REPEATED_TOKEN, &self.0)
+	}
+}
+
+/// Color value `c`, with channels in the order they are written in BLK text
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Deserialize)]
+pub struct Color {
+	pub r: u8,
+	pub g: u8,
+	pub b: u8,
+	pub a: u8,
+}
+
+impl Serialize for Color {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_newtype_struct(COLOR_TOKEN, &(self.r, self.g, self.b, self.a))
+	}
+}
+
+/// Intermediate result, as whether a sequence is a vector or repeated keys is only known once it is placed in a struct
+enum Node {
+	Value(BlkType),
+	Block(Vec<BlkField>),
+	Seq(Vec<Node>),
+	Repeated(Vec<Node>),
+	Omitted,
+}
+
+impl Node {
+	fn as_vector(&self) -> Option<BlkType> {
+		let Node::Seq(items) = self else {
+			return None;
+		};
+		let mut ints = [0; 4];
+		let mut floats = [0.0; 4];
+		let mut all_ints = true;
+		for (i, item) in items.iter().enumerate().take(4) {
+			match item {
+				Node::Value(BlkType::Int(v)) => {
+					ints[i] = *v;
+					floats[i] = *v as f32;
+				},
+				Node::Value(BlkType::Float(v)) => {
+					floats[i] = *v;
+					all_ints = false;
+				},
+				_ => return matrix(items),
+			}
+		}
+		Some(match (items.len(), all_ints) {
+			(2, true) => BlkType::Int2([ints[0], ints[1]]),
+			(3, true) => BlkType::Int3([ints[0], ints[1], ints[2]]),
+			(4, true) => BlkType::Int4(Box::new(ints)),
+			(2, false) => BlkType::Float2([floats[0], floats[1]]),
+			(3, false) => BlkType::Float3([floats[0], floats[1], floats[2]]),
+			(4, false) => BlkType::Float4(Box::new(floats)),
+			_ => return None,
+		})
+	}
+}
+
+fn matrix(rows: &[Node]) -> Option<BlkType> {
+	if rows.len() != 4 {
+		return None;
+	}
+	let mut out = [0.0; 12];
+	for (i, row) in rows.iter().enumerate() {
+		let row = match row.as_vector()? {
+			BlkType::Float3(row) => row,
+			BlkType::Int3(row) => row.map(|v| v as f32),
+			_ => return None,
+		};
+		out[i * 3..i * 3 + 3].copy_from_slice(&row);
+	}
+	Some(BlkType::Float12(Box::new(out)))
+}
+
+// Places a node as field `key`, expanding repeated keys
+fn push_field(fields: &mut Vec<BlkField>, key: &BlkString, node: Node) {
+	match node {
+		Node::Value(value) => fields.push(BlkField::Value(key.clone(), value)),
+		Node::Block(block) => fields.push(BlkField::Struct(key.clone(), block)),
+		Node::Seq(_) if let Some(vector) = node.as_vector() => {
+			fields.push(BlkField::Value(key.clone(), vector))
+		},
+		Node::Seq(items) | Node::Repeated(items) => {
+			for item in items {
+				push_field(fields, key, item);
+			}
+		},
+		Node::Omitted => {},
+	}
+}
+
+struct NodeSerializer;
+
+impl Serializer for NodeSerializer {
+	type Error = SerializeError;
+	type Ok = Node;
+	type SerializeMap = BlockBuilder;
+	type SerializeSeq = SeqBuilder;
+	type SerializeStruct = BlockBuilder;
+	type SerializeStructVariant = BlockBuilder;
+	type SerializeTuple = SeqBuilder;
+	type SerializeTupleStruct = SeqBuilder;
+	type SerializeTupleVariant = SeqBuilder;
+
+	fn serialize_bool(self, v: bool) -> Result<Node, Self::Error> {
+		Ok(Node::Value(BlkType::Bool(v)))
+	}
+
+	fn serialize_i8(self, v: i8) -> Result<Node, Self::Error> {
+		self.serialize_i32(v.into())
+	}
+
+	fn serialize_i16(self, v: i16) -> Result<Node, Self::Error> {
+		self.serialize_i32(v.into())
+	}
+
+	fn serialize_i32(self, v: i32) -> Result<Node, Self::Error> {
+		Ok(Node::Value(BlkType::Int(v)))
+	}
+
+	fn serialize_i64(self, v: i64) -> Result<Node, Self::Error> {
+		Ok(Node::Value(BlkType::Long(v)))
+	}
+
+	fn serialize_u8(self, v: u8) -> Result<Node, Self::Error> {
+		self.serialize_i32(v.into())
+	}
+
+	fn serialize_u16(self, v: u16) -> Result<Node, Self::Error> {
+		self.serialize_i32(v.into())
+	}
+
+	fn serialize_u32(self, v: u32) -> Result<Node, Self::Error> {
+		self.serialize_i64(v.into())
+	}
+
+	fn serialize_u64(self, v: u64) -> Result<Node, Self::Error> {
+		self.serialize_i64(
+			v.try_into()
+				.map_err(|_| SerializeError::custom(format!("{v} exceeds the range of i64")))?,
+		)
+	}
+
+	fn serialize_f32(self, v: f32) -> Result<Node, Self::Error> {
+		Ok(Node::Value(BlkType::Float(v)))
+	}
+
+	fn serialize_f64(self, v: f64) -> Result<Node, Self::Error> {
+		self.serialize_f32(v as f32)
+	}
+
+	fn serialize_char(self, v: char) -> Result<Node, Self::Error> {
+		self.serialize_str(v.encode_utf8(&mut [0; 4]))
+	}
+
+	fn serialize_str(self, v: &str) -> Result<Node, Self::Error> {
+		Ok(Node::Value(BlkType::Str(blk_str(v))))
+	}
+
+	fn serialize_bytes(self, _v: &[u8]) -> Result<Node, Self::Error> {
+		Err(SerializeError::custom("BLK has no byte array type"))
+	}
+
+	fn serialize_none(self) -> Result<Node, Self::Error> {
+		Ok(Node::Omitted)
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Node, Self::Error> {
+		value.serialize(self)
+	}
+
+	fn serialize_unit(self) -> Result<Node, Self::Error> {
+		Ok(Node::Omitted)
+	}
+
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<Node, Self::Error> {
+		Ok(Node::Omitted)
+	}
+
+	fn serialize_unit_variant(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+	) -> Result<Node, Self::Error> {
+		self.serialize_str(variant)
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(
+		self,
+		name: &'static str,
+		value: &T,
+	) -> Result<Node, Self::Error> {
+		let node = value.serialize(self)?;
+		match (name, node) {
+			(REPEATED_TOKEN, Node::Seq(items)) => Ok(Node::Repeated(items)),
+			(COLOR_TOKEN, node) => match node.as_vector() {
+				// Red and blue are swapped in memory, see `BlkType::fmt_with`
+				Some(BlkType::Int4(c)) => Ok(Node::Value(BlkType::Color {
+					r: c[2] as u8,
+					g: c[1] as u8,
+					b: c[0] as u8,
+					a: c[3] as u8,
+				})),
+				_ => Err(SerializeError::custom("Color has to consist of 4 channels")),
+			},
+			(_, node) => Ok(node),
+		}
+	}
+
+	fn serialize_newtype_variant<T: Serialize + ?Sized>(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+		value: &T,
+	) -> Result<Node, Self::Error> {
+		let mut fields = vec![];
+		push_field(&mut fields, &blk_str(variant), value.serialize(self)?);
+		Ok(Node::Block(fields))
+	}
+
+	fn serialize_seq(self, len: Option<usize>) -> Result<SeqBuilder, Self::Error> {
+		Ok(SeqBuilder {
+			items:   Vec::with_capacity(len.unwrap_or_default()),
+			variant: None,
+		})
+	}
+
+	fn serialize_tuple(self, len: usize) -> Result<SeqBuilder, Self::Error> {
+		self.serialize_seq(Some(len))
+	}
+
+	fn serialize_tuple_struct(
+		self,
+		_name: &'static str,
+		len: usize,
+	) -> Result<SeqBuilder, Self::Error> {
+		self.serialize_seq(Some(len))
+	}
+
+	fn serialize_tuple_variant(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+		len: usize,
+	) -> Result<SeqBuilder, Self::Error> {
+		Ok(SeqBuilder {
+			items:   Vec::with_capacity(len),
+			variant: Some(variant),
+		})
+	}
+
+	fn serialize_map(self, len: Option<usize>) -> Result<BlockBuilder, Self::Error> {
+		Ok(BlockBuilder {
+			fields:  Vec::with_capacity(len.unwrap_or_default()),
+			key:     None,
+			variant: None,
+		})
+	}
+
+	fn serialize_struct(
+		self,
+		_name: &'static str,
+		len: usize,
+	) -> Result<BlockBuilder, Self::Error> {
+		self.serialize_map(Some(len))
+	}
+
+	fn serialize_struct_variant(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+		len: usize,
+	) -> Result<BlockBuilder, Self::Error> {
+		Ok(BlockBuilder {
+			fields:  Vec::with_capacity(len),
+			key:     None,
+			variant: Some(variant),
+		})
+	}
+}
+
+struct SeqBuilder {
+	items:   Vec<Node>,
+	variant: Option<&'static str>,
+}
+
+impl SeqBuilder {
+	fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerializeError> {
+		let index = self.items.len();
+		self.items.push(
+			value
+				.serialize(NodeSerializer)
+				.map_err(|e| e.in_field(&index.to_string()))?,
+		);
+		Ok(())
+	}
+
+	fn finish(self) -> Result<Node, SerializeError> {
+		let seq = Node::Seq(self.items);
+		Ok(match self.variant {
+			Some(variant) => {
+				let mut fields = vec![];
+				push_field(&mut fields, &blk_str(variant), seq);
+				Node::Block(fields)
+			},
+			None => seq,
+		})
+	}
+}
+
+impl SerializeSeq for SeqBuilder {
+	type Error = SerializeError;
+	type Ok = Node;
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+		self.push(value)
+	}
+
+	fn end(self) -> Result<Node, Self::Error> {
+		self.finish()
+	}
+}
+
+impl SerializeTuple for SeqBuilder {
+	type Error = SerializeError;
+	type Ok = Node;
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+		self.push(value)
+	}
+
+	fn end(self) -> Result<Node, Self::Error> {
+		self.finish()
+	}
+}
+
+impl SerializeTupleStruct for SeqBuilder {
+	type Error = SerializeError;
+	type Ok = Node;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+		self.push(value)
+	}
+
+	fn end(self) -> Result<Node, Self::Error> {
+		self.finish()
+	}
+}
+
+impl SerializeTupleVariant for SeqBuilder {
+	type Error = SerializeError;
+	type Ok = Node;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+		self.push(value)
+	}
+
+	fn end(self) -> Result<Node, Self::Error> {
+		self.finish()
+	}
+}
+
+struct BlockBuilder {
+	fields:  Vec<BlkField>,
+	key:     Option<BlkString>,
+	variant: Option<&'static str>,
+}
+
+impl BlockBuilder {
+	fn push<T: Serialize + ?Sized>(
+		&mut self,
+		key: BlkString,
+		value: &T,
+	) -> Result<(), SerializeError> {
+		let node = value
+			.serialize(NodeSerializer)
+			.map_err(|e| e.in_field(&key))?;
+		push_field(&mut self.fields, &key, node);
+		Ok(())
+	}
+
+	fn finish(self) -> Result<Node, SerializeError> {
+		Ok(match self.variant {
+			Some(variant) => Node::Block(vec![BlkField::Struct(blk_str(variant), self.fields)]),
+			None => Node::Block(self.fields),
+		})
+	}
+}
+
+impl SerializeMap for BlockBuilder {
+	type Error = SerializeError;
+	type Ok = Node;
+
+	fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+		self.key = Some(key.serialize(KeySerializer)?);
+		Ok(())
+	}
+
+	fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+		let key = self
+			.key
+			.take()
+			.ok_or_else(|| SerializeError::custom("value serialized before its key"))?;
+		self.push(key, value)
+	}
+
+	fn end(self) -> Result<Node, Self::Error> {
+		self.finish()
+	}
+}
+
+impl SerializeStruct for BlockBuilder {
+	type Error = SerializeError;
+	type Ok = Node;
+
+	fn serialize_field<T: Serialize + ?Sized>(
+		&mut self,
+		key: &'static str,
+		value: &T,
+	) -> Result<(), Self::Error> {
+		self.push(blk_str(key), value)
+	}
+
+	fn end(self) -> Result<Node, Self::Error> {
+		self.finish()
+	}
+}
+
+impl SerializeStructVariant for BlockBuilder {
+	type Error = SerializeError;
+	type Ok = Node;
+
+	fn serialize_field<T: Serialize + ?Sized>(
+		&mut self,
+		key: &'static str,
+		value: &T,
+	) -> Result<(), Self::Error> {
+		self.push(blk_str(key), value)
+	}
+
+	fn end(self) -> Result<Node, Self::Error> {
+		self.finish()
+	}
+}
+
+/// Only accepts types that have an obvious string representation
+struct KeySerializer;
+
+macro_rules! key_to_string {
+	($($method:ident: $ty:ty)*) => {
+		$(
+			fn $method(self, v: $ty) -> Result<BlkString, Self::Error> {
+				Ok(blk_str(v.to_string()))
+			}
+		)*
+	};
+}
+
+macro_rules! invalid_key {
+	($($method:ident$(<$generic:ident>)?($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+		$(
+			fn $method$(<$generic: Serialize + ?Sized>)?(self, $($arg: $ty),*) -> Result<$ret, Self::Error> {
+				let _ = ($($arg),*);
+				Err(SerializeError::InvalidKey)
+			}
+		)*
+	};
+}
+
+impl Serializer for KeySerializer {
+	type Error = SerializeError;
+	type Ok = BlkString;
+	type SerializeMap = Impossible<BlkString, SerializeError>;
+	type SerializeSeq = Impossible<BlkString, SerializeError>;
+	type SerializeStruct = Impossible<BlkString, SerializeError>;
+	type SerializeStructVariant = Impossible<BlkString, SerializeError>;
+	type SerializeTuple = Impossible<BlkString, SerializeError>;
+	type SerializeTupleStruct = Impossible<BlkString, SerializeError>;
+	type SerializeTupleVariant = Impossible<BlkString, SerializeError>;
+
+	key_to_string! {
+		serialize_i8: i8 serialize_i16: i16 serialize_i32: i32 serialize_i64: i64
+		serialize_u8: u8 serialize_u16: u16 serialize_u32: u32 serialize_u64: u64
+		serialize_char: char serialize_str: &str
+	}
+
+	invalid_key! {
+		serialize_bool(v: bool) -> BlkString;
+		serialize_f32(v: f32) -> BlkString;
+		serialize_f64(v: f64) -> BlkString;
+		serialize_bytes(v: &[u8]) -> BlkString;
+		serialize_none() -> BlkString;
+		serialize_some<T>(v: &T) -> BlkString;
+		serialize_unit() -> BlkString;
+		serialize_unit_struct(name: &'static str) -> BlkString;
+		serialize_newtype_variant<T>(name: &'static str, index: u32, variant: &'static str, v: &T) -> BlkString;
+		serialize_seq(len: Option<usize>) -> Self::SerializeSeq;
+		serialize_tuple(len: usize) -> Self::SerializeTuple;
+		serialize_tuple_struct(name: &'static str, len: usize) -> Self::SerializeTupleStruct;
+		serialize_tuple_variant(name: &'static str, index: u32, variant: &'static str, len: usize) -> Self::SerializeTupleVariant;
+		serialize_map(len: Option<usize>) -> Self::SerializeMap;
+		serialize_struct(name: &'static str, len: usize) -> Self::SerializeStruct;
+		serialize_struct_variant(name: &'static str, index: u32, variant: &'static str, len: usize) -> Self::SerializeStructVariant;
+	}
+
+	fn serialize_unit_variant(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+	) -> Result<BlkString, Self::Error> {
+		Ok(blk_str(variant))
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(
+		self,
+		_name: &'static str,
+		value: &T,
+	) -> Result<BlkString, Self::Error> {
+		value.serialize(self)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::BTreeMap;
+
+	use serde::{Deserialize, Serialize};
+
+	use crate::blk::{
+		blk_string::blk_str,
+		blk_structure::BlkField,
+		blk_type::BlkType,
+		error::SerializeError,
+		from_blk,
+		make_strict_test,
+		serde_blk::{Color, Repeated, to_blk},
+	};
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct Root {
+		vec4f: [f32; 4],
+		int:   i32,
+		long:  i64,
+		alpha: Alpha,
+		beta:  Beta,
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct Alpha {
+		str:   String,
+		bool:  bool,
+		color: Color,
+		gamma: Gamma,
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct Gamma {
+		vec2i:     (i32, i32),
+		vec2f:     [f32; 2],
+		transform: [[f32; 3]; 4],
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct Beta {
+		float: f32,
+		vec2i: [i32; 2],
+		vec3f: [f32; 3],
+	}
+
+	#[test]
+	fn strict() {
+		let expected = make_strict_test();
+		let root: Root = from_blk(&expected).unwrap();
+		assert_eq!(to_blk(&root).unwrap(), expected);
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct Mission {
+		weapon:   Vec<Weapon>,
+		repeated: Repeated<f32>,
+		unset:    Option<i32>,
+		kind:     Kind,
+		mount:    Mount,
+		extra:    BTreeMap<String, bool>,
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct Weapon {
+		trigger: String,
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	enum Kind {
+		Cannon,
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	enum Mount {
+		Turret { yaw: f32 },
+	}
+
+	#[test]
+	fn conventions() {
+		let mission = Mission {
+			weapon:   vec![
+				Weapon {
+					trigger: "machine gun".to_owned(),
+				},
+				Weapon {
+					trigger: "cannon".to_owned(),
+				},
+			],
+			repeated: Repeated(vec![1.0, 2.0, 3.0]),
+			unset:    None,
+			kind:     Kind::Cannon,
+			mount:    Mount::Turret { yaw: 90.0 },
+			extra:    BTreeMap::from([("7".to_owned(), true)]),
+		};
+		let blk = to_blk(&mission).unwrap();
+		let weapon = |trigger: &str| {
+			BlkField::Struct(
+				blk_str("weapon"),
+				vec![BlkField::Value(
+					blk_str("trigger"),
+					BlkType::Str(blk_str(trigger)),
+				)],
+			)
+		};
+		let repeated = |v| BlkField::Value(blk_str("repeated"), BlkType::Float(v));
+		assert_eq!(
+			blk,
+			BlkField::Struct(
+				blk_str("root"),
+				vec![
+					weapon("machine gun"),
+					weapon("cannon"),
+					repeated(1.0),
+					repeated(2.0),
+					repeated(3.0),
+					BlkField::Value(blk_str("kind"), BlkType::Str(blk_str("Cannon"))),
+					BlkField::Struct(
+						blk_str("mount"),
+						vec![BlkField::Struct(
+							blk_str("Turret"),
+							vec![BlkField::Value(blk_str("yaw"), BlkType::Float(90.0))]
+						)]
+					),
+					BlkField::Struct(
+						blk_str("extra"),
+						vec![BlkField::Value(blk_str("7"), BlkType::Bool(true))]
+					),
+				]
+			)
+		);
+		assert_eq!(from_blk::<Mission>(&blk).unwrap(), mission);
+	}
+
+	#[test]
+	fn errors() {
+		assert_eq!(to_blk(&5), Err(SerializeError::RootNotStruct));
+		assert_eq!(
+			to_blk(&BTreeMap::from([(7_u32, 'x')])).unwrap(),
+			BlkField::Struct(
+				blk_str("root"),
+				vec![BlkField::Value(blk_str("7"), BlkType::Str(blk_str("x")))]
+			)
+		);
+		assert_eq!(
+			to_blk(&BTreeMap::from([((1, 2), 3)])),
+			Err(SerializeError::InvalidKey)
+		);
+		assert_eq!(
+			to_blk(&BTreeMap::from([(1.5_f32.to_bits(), u64::MAX)])).map_err(|e| e.to_string()),
+			Err(format!(
+				"{}: {} exceeds the range of i64",
+				1.5_f32.to_bits(),
+				u64::MAX
+			))
+		);
+	}
+}
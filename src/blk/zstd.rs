@@ -1,7 +1,14 @@
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Read, Write};
 
-use color_eyre::{Report, eyre::ContextCompat};
-use zstd::{Decoder, dict::DecoderDictionary};
+use color_eyre::{
+	Report,
+	eyre::{ContextCompat, bail},
+};
+use zstd::{
+	Decoder,
+	Encoder,
+	dict::{DecoderDictionary, EncoderDictionary},
+};
 
 use crate::blk::file::FileType;
 
@@ -35,13 +42,61 @@ pub fn decode_zstd(
 	Ok(out)
 }
 
+/// Compresses a FAT or SLIM payload into the zstd variant of its file type, inverse of [`decode_zstd`]
+/// The payload is expected in the same form [`decode_zstd`] yields it,
+/// meaning FAT retains its leading file-type byte while SLIM does not
+pub fn encode_zstd(
+	file_type: FileType,
+	payload: &[u8],
+	frame_encoder: Option<&EncoderDictionary>,
+) -> Result<Vec<u8>, Report> {
+	if !file_type.is_zstd() {
+		bail!("File type: {file_type} is not zstd compressed");
+	}
+
+	let mut out = vec![file_type as u8];
+	if file_type.needs_dict() {
+		let mut encoder = Encoder::with_prepared_dictionary(
+			&mut out,
+			frame_encoder.context(format!(
+				"File type: {file_type} requires a dictionary, but none was passed"
+			))?,
+		)?;
+		encoder.write_all(payload)?;
+		encoder.finish()?;
+	} else {
+		let compressed = zstd::encode_all(payload, 0)?;
+		if !file_type.is_slim() {
+			// FAT_ZSTD is prefixed with the 24 bit little-endian length of the compressed frame
+			if compressed.len() > 0xFF_FFFF {
+				bail!(
+					"Compressed size of {} bytes exceeds the 24 bit length prefix",
+					compressed.len()
+				);
+			}
+			out.extend_from_slice(&(compressed.len() as u32).to_le_bytes()[..3]);
+		}
+		out.extend_from_slice(&compressed);
+	}
+	Ok(out)
+}
+
 #[cfg(test)]
 mod test {
 	use std::{fs, io::Read};
 
-	use zstd::{Decoder, dict::DecoderDictionary};
+	use zstd::{
+		Decoder,
+		dict::{DecoderDictionary, EncoderDictionary},
+	};
+
+	use crate::blk::{
+		file::FileType,
+		zstd::{decode_zstd, encode_zstd},
+	};
 
-	use crate::blk::{file::FileType, zstd::decode_zstd};
+	const DICT: &str =
+		"./samples/bfb732560ad45234690acad246d7b14c2f25ad418a146e5e7ef68ba3386a315c.dict";
 
 	#[test]
 	fn fat_zstd() {
@@ -71,10 +126,7 @@ mod test {
 	#[test]
 	fn slim_zstd_dict() {
 		let file = fs::read("./samples/section_slim_zst_dict.blk").unwrap();
-		let dict = fs::read(
-			"./samples/bfb732560ad45234690acad246d7b14c2f25ad418a146e5e7ef68ba3386a315c.dict",
-		)
-		.unwrap();
+		let dict = fs::read(DICT).unwrap();
 		let frame_decoder = DecoderDictionary::copy(&dict);
 
 		let mut decoder = Decoder::with_prepared_dictionary(&file[1..], &frame_decoder).unwrap();
@@ -83,4 +135,36 @@ mod test {
 		pretty_assertions::assert_eq!(&out, &include_bytes!("../../samples/section_slim.blk")[1..])
 		// Truncating the first byte, as it is magic byte for the SLIM format
 	}
+
+	#[test]
+	fn round_trip_all() {
+		let dict = fs::read(DICT).unwrap();
+		let frame_decoder = DecoderDictionary::copy(&dict);
+		let frame_encoder = EncoderDictionary::copy(&dict, 0);
+
+		for path in [
+			"./samples/section_fat_zst.blk",
+			"./samples/section_slim_zst.blk",
+			"./samples/section_slim_zst_dict.blk",
+		] {
+			let file = fs::read(path).unwrap();
+			let file_type = FileType::from_byte(file[0]).unwrap();
+			let decoded = decode_zstd(file_type, &file, Some(&frame_decoder)).unwrap();
+
+			let encoded = encode_zstd(file_type, &decoded, Some(&frame_encoder)).unwrap();
+			assert_eq!(encoded[0], file[0], "{path}");
+			pretty_assertions::assert_eq!(
+				decode_zstd(file_type, &encoded, Some(&frame_decoder)).unwrap(),
+				decoded,
+				"{path}"
+			);
+		}
+	}
+
+	#[test]
+	fn dict_required() {
+		let payload = &include_bytes!("../../samples/section_slim.blk")[1..];
+		assert!(encode_zstd(FileType::SLIM_ZST_DICT, payload, None).is_err());
+		assert!(encode_zstd(FileType::SLIM, payload, None).is_err());
+	}
 }
@@ -73,7 +73,7 @@
 //! # Continue reading
 //! To learn how the payload is used, read [`crate::vromf::inner_container`].
 
-use std::{io::Write, mem::size_of};
+use std::{io::Write, mem::size_of, ops::Range};
 
 use color_eyre::{
 	Report,
@@ -90,6 +90,24 @@ use crate::vromf::{
 };
 
 pub fn decode_bin_vromf(file: &[u8], validate: bool) -> Result<(Vec<u8>, Metadata), Report> {
+	let (inner, metadata) = decode_bin_vromf_lazy(file, validate)?;
+	let inner = match inner {
+		Inner::Range(range) => file[range].to_vec(),
+		Inner::Decoded(inner) => inner,
+	};
+	Ok((inner, metadata))
+}
+
+/// Inner container as returned by [`decode_bin_vromf_lazy`]
+pub(crate) enum Inner {
+	/// Payload is stored as is, at this range of the binary container
+	Range(Range<usize>),
+	/// Payload had to be de-obfuscated or decompressed
+	Decoded(Vec<u8>),
+}
+
+/// Same as [`decode_bin_vromf`], without copying payloads that are stored as is
+pub(crate) fn decode_bin_vromf_lazy(file: &[u8], validate: bool) -> Result<(Inner, Metadata), Report> {
 	let mut metadata = Metadata::default();
 
 	let mut ptr = 0_usize;
@@ -126,7 +144,7 @@ pub fn decode_bin_vromf(file: &[u8], validate: bool) -> Result<(Vec<u8>, Metadat
 	let (pack_type, extended_header_size) = pack_type_from_aligned(header_packed)?;
 	metadata.packing = Some(pack_type);
 
-	let inner_data = if header_type.is_extended() {
+	let inner_range = if header_type.is_extended() {
 		let extended_header = idx_file_offset(
 			&mut ptr,
 			size_of::<u16>() + size_of::<u16>() + size_of::<u32>(),
@@ -152,24 +170,28 @@ pub fn decode_bin_vromf(file: &[u8], validate: bool) -> Result<(Vec<u8>, Metadat
 
 		// Null length means the remaining bytes are used
 		if extended_header_size == 0 {
-			&file[ptr..]
+			ptr..file.len()
 		} else {
-			idx_file_offset(&mut ptr, extended_header_size as usize)?
+			let start = ptr;
+			idx_file_offset(&mut ptr, extended_header_size as usize)?;
+			start..ptr
 		}
 	} else {
+		let start = ptr;
 		if pack_type.is_compressed() {
-			idx_file_offset(&mut ptr, extended_header_size as usize)?
+			idx_file_offset(&mut ptr, extended_header_size as usize)?;
 		} else {
-			idx_file_offset(&mut ptr, size as usize)?
+			idx_file_offset(&mut ptr, size as usize)?;
 		}
+		start..ptr
 	};
 
 	// Directly return when data is not obfuscated
 	if !pack_type.is_obfuscated() {
-		return Ok((inner_data.to_vec(), metadata));
+		return Ok((Inner::Range(inner_range), metadata));
 	}
 
-	let mut output = inner_data.to_vec();
+	let mut output = file[inner_range].to_vec();
 	deobfuscate(&mut output);
 
 	if pack_type.is_compressed() {
@@ -188,7 +210,7 @@ pub fn decode_bin_vromf(file: &[u8], validate: bool) -> Result<(Vec<u8>, Metadat
 		}
 	}
 
-	Ok((output, metadata))
+	Ok((Inner::Decoded(output), metadata))
 }
 
 pub fn encode_bin_vromf(input: &[u8], meta: Metadata) -> Result<Vec<u8>, Report> {
@@ -212,8 +234,13 @@ pub fn encode_bin_vromf(input: &[u8], meta: Metadata) -> Result<Vec<u8>, Report>
 		compressed
 	};
 
-	// Compression info: top 6 bits = packing, lower 26 bits = payload size
-	let compression_info = (packing as u32) << 26 | (payload.len() as u32 & 0x03FF_FFFF);
+	// Compression info: top 6 bits = packing, lower 26 bits = payload size, which is 0 when uncompressed
+	let packed_size = if packing.is_compressed() {
+		payload.len() as u32
+	} else {
+		0
+	};
+	let compression_info = (packing as u32) << 26 | (packed_size & 0x03FF_FFFF);
 	output.write_all(&compression_info.to_le_bytes())?;
 
 	// Extended header (if VRFX)
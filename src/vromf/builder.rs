@@ -0,0 +1,481 @@
+use std::{
+	collections::{HashMap, HashSet},
+	ffi::OsStr,
+	fs,
+	path::{Component, Path, PathBuf},
+	str::FromStr,
+};
+
+use color_eyre::{
+	Report,
+	eyre::{Context, ContextCompat, bail, eyre},
+};
+use wt_version::Version;
+
+use crate::{
+	blk,
+	blk::{
+		EncoderDictionary,
+		binary_serialize::writer::SlimNameTable,
+		blk_structure::BlkField,
+		error::TextParseError,
+		file::FileType,
+		plaintext_deserialize::{deserialize_blk, json::JsonInference},
+		util::maybe_blk,
+		zstd::encode_zstd,
+	},
+	vromf::{
+		File,
+		HeaderType,
+		Packing,
+		PlatformType,
+		VromfUnpacker,
+		binary_container::encode_bin_vromf,
+		header::Metadata,
+		inner_container::encode_inner_vromf,
+	},
+};
+
+/// Packs a collection of files into a vromf image, the inverse of [`crate::vromf::VromfUnpacker`]
+///
+/// Paths are normalized to be relative and `/` separated.
+/// The output is ordered like the game does: the `.dict` comes first, `nm` last, and all remaining files are sorted by path in between.
+#[derive(Debug, Clone)]
+pub struct VromfBuilder {
+	files:    Vec<File>,
+	// Normalized path of each file, for rejecting duplicates
+	paths:    HashSet<PathBuf>,
+	metadata: Metadata,
+}
+
+impl Default for VromfBuilder {
+	fn default() -> Self {
+		Self {
+			files:    vec![],
+			paths:    HashSet::new(),
+			metadata: Metadata {
+				header_type: Some(HeaderType::VRFX),
+				platform:    Some(PlatformType::Pc),
+				packing:     Some(Packing::ZSTD_OBFS),
+				version:     None,
+				digest:      Some(false),
+			},
+		}
+	}
+}
+
+impl VromfBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Recursively reads all files from a directory, paths in the image are relative to `root`
+	pub fn from_dir(root: impl AsRef<Path>) -> Result<Self, Report> {
+		let root = root.as_ref();
+		let mut files = vec![];
+		let mut pending = vec![root.to_path_buf()];
+		while let Some(dir) = pending.pop() {
+			for entry in fs::read_dir(&dir)? {
+				let entry = entry?;
+				if entry.file_type()?.is_dir() {
+					pending.push(entry.path());
+					continue;
+				}
+				let (path, buf) = File::new(entry.path())?.split();
+				files.push(File::from_raw(path.strip_prefix(root)?.to_path_buf(), buf));
+			}
+		}
+		Self::from_files(files)
+	}
+
+	pub fn from_files(files: impl IntoIterator<Item = File>) -> Result<Self, Report> {
+		let mut builder = Self::default();
+		for file in files {
+			builder.add_file(file)?;
+		}
+		Ok(builder)
+	}
+
+	/// Adds a single file, failing when its path is already taken
+	pub fn add_file(&mut self, file: File) -> Result<(), Report> {
+		let (path, buf) = file.split();
+		let path = normalize_path(&path)?;
+		if !self.paths.insert(path.clone()) {
+			bail!("Duplicate file path {}", path.display());
+		}
+		self.files.push(File::from_raw(path, buf));
+		Ok(())
+	}
+
+	/// Takes over all settings, for example to repack an image with [`crate::vromf::VromfUnpacker::metadata`]
+	pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+		self.metadata = metadata;
+		self
+	}
+
+	pub fn header_type(mut self, header_type: HeaderType) -> Self {
+		self.metadata.header_type = Some(header_type);
+		self
+	}
+
+	pub fn platform(mut self, platform: PlatformType) -> Self {
+		self.metadata.platform = Some(platform);
+		self
+	}
+
+	pub fn packing(mut self, packing: Packing) -> Self {
+		self.metadata.packing = Some(packing);
+		self
+	}
+
+	/// Only used by [`HeaderType::VRFX`], defaults to the contents of the `version` file when unset
+	pub fn version(mut self, version: Version) -> Self {
+		self.metadata.version = Some(version);
+		self
+	}
+
+	/// Whether the inner container stores a SHA1 digest for each file
+	pub fn digest(mut self, digest: bool) -> Self {
+		self.metadata.digest = Some(digest);
+		self
+	}
+
+	/// Converts plaintext BLK back into the binary kind the same path has in `original`
+	///
+	/// Output of [`crate::vromf::BlkOutputFormat::BlkText`], [`crate::vromf::BlkOutputFormat::Json`] and [`crate::vromf::BlkOutputFormat::JsonTyped`] is accepted,
+	/// files that are already binary or absent from `original` are left as is.
+	/// Keys and strings missing from the name map of SLIM files are appended to it, re-encoding `nm` in that case.
+	/// Existing indices are kept, so untouched files remain valid.
+	pub fn binarize_like(mut self, original: &VromfUnpacker) -> Result<Self, Report> {
+		let originals = original
+			.raw_files()
+			.iter()
+			.map(|e| (e.path(), e))
+			.collect::<HashMap<_, _>>();
+		let dict = original
+			.raw_files()
+			.iter()
+			.find(|e| e.path().extension() == Some(OsStr::new("dict")))
+			.map(File::buf);
+		let encoder_dict = dict.map(|e| EncoderDictionary::copy(e, 0));
+		let mut names = original.nm().map(SlimNameTable::collecting);
+
+		for file in &mut self.files {
+			let Some(&reference_file) = originals.get(file.path()) else {
+				continue;
+			};
+			if maybe_blk(file) || !maybe_blk(reference_file) {
+				continue;
+			}
+			let context = || format!("binarizing {}", file.path().display());
+
+			let file_type = FileType::from_byte(reference_file.buf()[0])?;
+			let reference = blk::unpack_blk(
+				&mut reference_file.buf().to_vec(),
+				original.dict(),
+				original.nm(),
+			)
+			.with_context(context)?;
+
+			let text = str::from_utf8(file.buf()).with_context(context)?;
+			let parsed = match text.trim_start().as_bytes().first() {
+				Some(b'{') => BlkField::from_json(
+					&serde_json::from_str(text)?,
+					JsonInference::standard(),
+					Some(&reference),
+				),
+				Some(b'[') => BlkField::from_typed_json(&serde_json::from_str(text)?),
+				_ => deserialize_blk(text).map_err(TextParseError::into_report),
+			}
+			.with_context(context)?;
+
+			let payload = if file_type.is_slim() {
+				let names = names
+					.as_mut()
+					.context("SLIM BLK requires a name map, but the original has none")?;
+				parsed.to_slim_blk_with(names)?
+			} else {
+				parsed.to_fat_blk()?
+			};
+			*file.buf_mut() = match file_type {
+				FileType::FAT | FileType::SLIM => payload,
+				FileType::FAT_ZSTD => encode_zstd(file_type, &payload, None)?,
+				// Compressed SLIM files do not retain their leading file-type byte
+				FileType::SLIM_ZSTD | FileType::SLIM_ZST_DICT => {
+					encode_zstd(file_type, &payload[1..], encoder_dict.as_ref())?
+				},
+				FileType::BBF => bail!("BBF file type currently not supported"),
+			};
+		}
+
+		if let Some(names) = names
+			&& !names.new_names().is_empty()
+		{
+			let names = names.into_name_map();
+			let nm = match original
+				.raw_files()
+				.iter()
+				.find(|e| e.path() == Path::new("nm"))
+			{
+				Some(original) => names.encode_nm_file_like(dict, original.buf())?,
+				None => names.encode_nm_file(dict)?,
+			};
+			match self.files.iter_mut().find(|e| e.path() == Path::new("nm")) {
+				Some(file) => *file.buf_mut() = nm,
+				None => {
+					self.paths.insert(PathBuf::from("nm"));
+					self.files.push(File::from_raw(PathBuf::from("nm"), nm));
+				},
+			}
+		}
+		Ok(self)
+	}
+
+	pub fn files(&self) -> &[File] {
+		&self.files
+	}
+
+	/// Encodes the final `.vromfs.bin` image
+	pub fn build(self) -> Result<Vec<u8>, Report> {
+		let mut metadata = self.metadata;
+		let mut files = self.files;
+
+		if metadata.version.is_none() && metadata.header_type == Some(HeaderType::VRFX) {
+			let version_file = files
+				.iter()
+				.find(|e| e.path() == Path::new("version"))
+				.context(
+					"Extended header requires a version, but neither a version nor a version file was provided",
+				)?;
+			let s = String::from_utf8_lossy(version_file.buf());
+			metadata.version = Some(
+				Version::from_str(s.trim())
+					.map_err(|_| eyre!("Invalid version file contents: {s}"))?,
+			);
+		}
+
+		files.sort_by_cached_key(|e| {
+			let rank = if e.path().extension() == Some(OsStr::new("dict")) {
+				0
+			} else if e.path() == Path::new("nm") {
+				2
+			} else {
+				1
+			};
+			(rank, e.path().to_path_buf())
+		});
+
+		let digest_header = if metadata.digest.unwrap_or_default() {
+			0x30
+		} else {
+			0x20
+		};
+		let inner = encode_inner_vromf(files, digest_header)?;
+		encode_bin_vromf(&inner, metadata)
+	}
+}
+
+// Strips leading `/` or `./` and unifies separators, rejecting paths escaping the image root
+fn normalize_path(path: &Path) -> Result<PathBuf, Report> {
+	let raw = path
+		.to_str()
+		.context("File path is not valid UTF-8")?
+		.replace('\\', "/");
+	let mut normalized = vec![];
+	for component in Path::new(&raw).components() {
+		match component {
+			Component::Normal(part) => normalized.push(part.to_str().expect("Infallible")),
+			Component::CurDir | Component::RootDir => {},
+			Component::ParentDir | Component::Prefix(_) => {
+				bail!("File path {} leaves the image root", path.display())
+			},
+		}
+	}
+	if normalized.is_empty() {
+		bail!("Empty file path");
+	}
+	Ok(PathBuf::from(normalized.join("/")))
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::{Path, PathBuf};
+
+	use wt_version::Version;
+
+	use crate::{
+		blk::error::NmDigestError,
+		vromf::{
+			BlkOutputFormat,
+			File,
+			FileFilter,
+			HeaderType,
+			Packing,
+			VromfBuilder,
+			VromfUnpacker,
+		},
+	};
+
+	const DICT_SAMPLE: &str = "./samples/checked_simple_uncompressed_checked.vromfs.bin";
+	const BLK_PATH: &str = "config/section_slim_zst_dict.blk";
+
+	// Unpacks, lets the caller edit the plaintext, and repacks into binary BLK
+	fn repack_plaintext(
+		format: BlkOutputFormat,
+		edit: impl Fn(&mut File),
+	) -> (Vec<u8>, VromfUnpacker) {
+		let original =
+			VromfUnpacker::from_file(&File::new(DICT_SAMPLE).unwrap(), true, false).unwrap();
+		let mut files = original
+			.clone()
+			.unpack_all(Some(format), false, FileFilter::All)
+			.unwrap();
+		files.iter_mut().for_each(edit);
+		let packed = VromfBuilder::from_files(files)
+			.unwrap()
+			.with_metadata(original.metadata().clone())
+			.binarize_like(&original)
+			.unwrap()
+			.build()
+			.unwrap();
+		(packed, original)
+	}
+
+	#[test]
+	fn pack_dir() {
+		let packed = VromfBuilder::from_dir("./samples/rendist")
+			.unwrap()
+			.version(Version::new(2, 45, 0, 1))
+			.digest(true)
+			.build()
+			.unwrap();
+		let file = File::from_raw("rendist.vromfs.bin".into(), packed);
+		// The sample dictionary is truncated to 1 MiB, so only its filename matches the nm header
+		let err = VromfUnpacker::from_file(&file, true, false).unwrap_err();
+		assert!(matches!(
+			err.downcast_ref::<NmDigestError>(),
+			Some(NmDigestError::DictMismatch { .. })
+		));
+		let unpacker = VromfUnpacker::from_file(&file, false, false).unwrap();
+		unpacker.validate_nm_digests().unwrap();
+		assert_eq!(unpacker.metadata().version, Some(Version::new(2, 45, 0, 1)));
+		assert_eq!(unpacker.metadata().digest, Some(true));
+
+		let files = unpacker.unpack_all(None, false, FileFilter::All).unwrap();
+		let paths = files.iter().map(|e| e.path()).collect::<Vec<_>>();
+		assert_eq!(
+			paths,
+			[
+				Path::new("ca35013aabca60792d5203b0137d0a8720d1dc151897eb856b12318891d08466.dict"),
+				Path::new("rendinst_dmg.blk"),
+				Path::new("nm"),
+			]
+		);
+		assert_eq!(
+			files[1].buf(),
+			std::fs::read("./samples/rendist/rendinst_dmg.blk").unwrap()
+		);
+	}
+
+	#[test]
+	fn repack_plain() {
+		let file = File::new("./samples/checked_simple_uncompressed_checked.vromfs.bin").unwrap();
+		let unpacker = VromfUnpacker::from_file(&file, true, false).unwrap();
+		let metadata = unpacker.metadata().clone();
+		let files = unpacker.unpack_all(None, false, FileFilter::All).unwrap();
+		let repacked = VromfBuilder::from_files(files.into_iter().rev())
+			.unwrap()
+			.with_metadata(metadata)
+			.build()
+			.unwrap();
+		assert_eq!(repacked, file.buf());
+	}
+
+	#[test]
+	fn normalized_paths() {
+		let builder = VromfBuilder::from_files([
+			File::from_raw(PathBuf::from("./config\\a.blk"), vec![]),
+			File::from_raw(PathBuf::from("/b.blk"), vec![]),
+		])
+		.unwrap()
+		.header_type(HeaderType::VRFS)
+		.packing(Packing::PLAIN);
+		let paths = builder.files().iter().map(|e| e.path()).collect::<Vec<_>>();
+		assert_eq!(paths, [Path::new("config/a.blk"), Path::new("b.blk")]);
+
+		let mut builder = builder;
+		assert!(
+			builder
+				.add_file(File::from_raw(PathBuf::from("config/a.blk"), vec![]))
+				.is_err()
+		);
+		assert!(
+			builder
+				.add_file(File::from_raw(PathBuf::from("../c.blk"), vec![]))
+				.is_err()
+		);
+		builder.build().unwrap();
+	}
+
+	#[test]
+	fn binarize_text() {
+		let (packed, original) = repack_plaintext(BlkOutputFormat::BlkText, |_| {});
+		let repacked =
+			VromfUnpacker::from_file(&File::from_raw("a".into(), packed), true, false).unwrap();
+		for unpacker in [&original, &repacked] {
+			// Still stored in its compressed form
+			let raw = unpacker
+				.unpack_one(Path::new(BLK_PATH), None, false)
+				.unwrap();
+			assert_eq!(raw.buf()[0], 0x05);
+		}
+		let unpack = |e: &VromfUnpacker| {
+			e.unpack_one(Path::new(BLK_PATH), Some(BlkOutputFormat::BlkText), false)
+				.unwrap()
+		};
+		assert_eq!(unpack(&original).buf(), unpack(&repacked).buf());
+	}
+
+	#[test]
+	fn binarize_json() {
+		let (packed, original) = repack_plaintext(BlkOutputFormat::Json, |_| {});
+		let repacked =
+			VromfUnpacker::from_file(&File::from_raw("a".into(), packed), true, false).unwrap();
+		let unpack = |e: &VromfUnpacker| {
+			e.unpack_one(Path::new(BLK_PATH), Some(BlkOutputFormat::BlkText), false)
+				.unwrap()
+		};
+		assert_eq!(unpack(&original).buf(), unpack(&repacked).buf());
+	}
+
+	#[test]
+	fn binarize_typed_json() {
+		let (packed, original) = repack_plaintext(BlkOutputFormat::JsonTyped, |_| {});
+		let repacked =
+			VromfUnpacker::from_file(&File::from_raw("a".into(), packed), true, false).unwrap();
+		let unpack = |e: &VromfUnpacker| {
+			e.unpack_one(Path::new(BLK_PATH), Some(BlkOutputFormat::JsonTyped), false)
+				.unwrap()
+		};
+		assert_eq!(unpack(&original).buf(), unpack(&repacked).buf());
+	}
+
+	#[test]
+	fn binarize_new_name() {
+		let (packed, original) = repack_plaintext(BlkOutputFormat::BlkText, |file| {
+			if file.path() == Path::new(BLK_PATH) {
+				file.buf_mut()
+					.extend_from_slice(b"\nbrand_new:t = \"value\"\n");
+			}
+		});
+		let repacked =
+			VromfUnpacker::from_file(&File::from_raw("a".into(), packed), true, false).unwrap();
+		repacked.validate_nm_digests().unwrap();
+		let nm = repacked.nm().unwrap();
+		assert_eq!(nm.parsed.len(), original.nm().unwrap().parsed.len() + 2);
+		let text = repacked
+			.unpack_one(Path::new(BLK_PATH), Some(BlkOutputFormat::BlkText), false)
+			.unwrap();
+		assert!(String::from_utf8_lossy(text.buf()).contains("brand_new:t = \"value\""));
+	}
+}
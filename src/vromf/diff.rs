@@ -0,0 +1,171 @@
+use std::{
+	collections::HashMap,
+	ffi::OsStr,
+	fmt::{Display, Formatter},
+	path::{Path, PathBuf},
+};
+
+use color_eyre::{Report, eyre::Context};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Serialize, Serializer};
+use wt_version::Version;
+
+use crate::{
+	blk::{
+		blk_structure::BlkField,
+		diff::{BlkChange, DiffOptions},
+		include::BlkLoader,
+	},
+	vromf::VromfUnpacker,
+};
+
+/// Files that differ between two images, see [`VromfUnpacker::diff`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VromfDiff {
+	/// [`VromfUnpacker::latest_version`] of the image diffed from
+	pub old_version: Option<Version>,
+	/// [`VromfUnpacker::latest_version`] of the image diffed against
+	pub new_version: Option<Version>,
+	pub added:       Vec<PathBuf>,
+	pub removed:     Vec<PathBuf>,
+	pub changed:     Vec<ChangedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedFile {
+	pub path:        PathBuf,
+	#[serde(serialize_with = "hex")]
+	pub old_digest:  [u8; 20],
+	#[serde(serialize_with = "hex")]
+	pub new_digest:  [u8; 20],
+	/// Structural changes of `.blk` files, when requested using [`VromfDiffOptions::blk`]
+	pub blk_changes: Option<Vec<BlkChange>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VromfDiffOptions {
+	/// Diffs changed `.blk` files structurally using these options, which requires unpacking them
+	pub blk: Option<DiffOptions>,
+}
+
+impl VromfUnpacker {
+	/// Compares the files of two images by their SHA1, treating `self` as the older one
+	pub fn diff(&self, other: &VromfUnpacker) -> Result<VromfDiff, Report> {
+		self.diff_with(other, VromfDiffOptions::default())
+	}
+
+	pub fn diff_with(
+		&self,
+		other: &VromfUnpacker,
+		options: VromfDiffOptions,
+	) -> Result<VromfDiff, Report> {
+		let old = self.digests_by_path();
+		let new = other.digests_by_path();
+
+		let mut added: Vec<PathBuf> = new
+			.keys()
+			.filter(|path| !old.contains_key(*path))
+			.map(|path| path.to_path_buf())
+			.collect();
+		let mut removed: Vec<PathBuf> = old
+			.keys()
+			.filter(|path| !new.contains_key(*path))
+			.map(|path| path.to_path_buf())
+			.collect();
+		let mut changed: Vec<ChangedFile> = old
+			.iter()
+			.filter_map(|(path, old_digest)| {
+				let new_digest = new.get(path)?;
+				(old_digest != new_digest).then(|| ChangedFile {
+					path:        path.to_path_buf(),
+					old_digest:  *old_digest,
+					new_digest:  *new_digest,
+					blk_changes: None,
+				})
+			})
+			.collect();
+		added.sort_unstable();
+		removed.sort_unstable();
+		changed.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+
+		if let Some(blk_options) = options.blk {
+			changed = changed
+				.into_par_iter()
+				.map(|mut file| {
+					if file.path.extension() == Some(OsStr::new("blk")) {
+						file.blk_changes = Some(self.diff_blk(other, &file.path, blk_options)?);
+					}
+					Ok(file)
+				})
+				.collect::<Result<_, Report>>()?;
+		}
+
+		Ok(VromfDiff {
+			old_version: self.latest_version()?,
+			new_version: other.latest_version()?,
+			added,
+			removed,
+			changed,
+		})
+	}
+
+	fn digests_by_path(&self) -> HashMap<&Path, [u8; 20]> {
+		self.raw_files()
+			.iter()
+			.enumerate()
+			.map(|(i, file)| (file.path(), self.file_digest(i)))
+			.collect()
+	}
+
+	fn diff_blk(
+		&self,
+		other: &VromfUnpacker,
+		path: &Path,
+		options: DiffOptions,
+	) -> Result<Vec<BlkChange>, Report> {
+		let path = path.to_string_lossy();
+		let old = self
+			.load(&path)
+			.with_context(|| format!("unpacking old {path}"))?;
+		let new = other
+			.load(&path)
+			.with_context(|| format!("unpacking new {path}"))?;
+		Ok(BlkField::diff_with(&old, &new, options))
+	}
+}
+
+fn hex<S: Serializer>(digest: &[u8; 20], serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str(
+		&digest
+			.iter()
+			.map(|b| format!("{b:02x}"))
+			.collect::<String>(),
+	)
+}
+
+/// Patch-notes style summary, listing structural changes indented below their file
+impl Display for VromfDiff {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let version =
+			|v: &Option<Version>| v.map_or_else(|| "unknown".to_owned(), |v| v.to_string());
+		writeln!(
+			f,
+			"Version {} -> {}",
+			version(&self.old_version),
+			version(&self.new_version)
+		)?;
+		for path in &self.added {
+			writeln!(f, "+ {}", path.display())?;
+		}
+		for path in &self.removed {
+			writeln!(f, "- {}", path.display())?;
+		}
+		for file in &self.changed {
+			writeln!(f, "~ {}", file.path.display())?;
+			for change in file.blk_changes.iter().flatten() {
+				writeln!(f, "\t{change}")?;
+			}
+		}
+		Ok(())
+	}
+}
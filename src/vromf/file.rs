@@ -1,18 +1,40 @@
 use std::{
+	fmt::{Debug, Formatter},
 	fs,
 	fs::Metadata,
 	io::Read,
+	ops::Range,
 	path::{Path, PathBuf},
+	sync::Arc,
 };
 
+/// Buffer multiple files can point into, such as a decompressed image or a memory mapping
+pub type SharedBuf = Arc<dyn AsRef<[u8]> + Send + Sync>;
+
 #[derive(Debug, Clone)]
 pub struct File {
 	path: PathBuf,
-	file: Vec<u8>,
+	file: Payload,
 	// Present when read from disk, not present when created from VROMF
 	meta: Option<Metadata>,
 }
 
+#[derive(Clone)]
+enum Payload {
+	Owned(Vec<u8>),
+	// Range of a buffer shared with other files, copied once mutated
+	Shared(SharedBuf, Range<usize>),
+}
+
+impl Debug for Payload {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Payload::Owned(buf) => f.debug_tuple("Owned").field(buf).finish(),
+			Payload::Shared(_, range) => f.debug_tuple("Shared").field(range).finish(),
+		}
+	}
+}
+
 impl File {
 	pub fn new(p: impl Into<PathBuf>) -> color_eyre::Result<Self> {
 		let path = p.into();
@@ -20,7 +42,7 @@ impl File {
 		let mut buf = Vec::with_capacity(1024);
 		f.read_to_end(&mut buf)?;
 		Ok(Self {
-			file: buf,
+			file: Payload::Owned(buf),
 			path,
 			meta: Some(f.metadata()?),
 		})
@@ -29,7 +51,7 @@ impl File {
 	pub fn from_raw_with_meta(path: PathBuf, file: Vec<u8>, meta: Metadata) -> Self {
 		Self {
 			path,
-			file,
+			file: Payload::Owned(file),
 			meta: Some(meta),
 		}
 	}
@@ -37,13 +59,32 @@ impl File {
 	pub fn from_raw(path: PathBuf, file: Vec<u8>) -> Self {
 		Self {
 			path,
-			file,
+			file: Payload::Owned(file),
+			meta: None,
+		}
+	}
+
+	/// File pointing into `range` of a shared buffer, which is only copied once the file is mutated or split
+	///
+	/// Panics if `range` exceeds the buffer
+	pub fn from_shared(path: PathBuf, buf: SharedBuf, range: Range<usize>) -> Self {
+		assert!(
+			range.start <= range.end && range.end <= (*buf).as_ref().len(),
+			"Range {range:?} exceeds shared buffer"
+		);
+		Self {
+			path,
+			file: Payload::Shared(buf, range),
 			meta: None,
 		}
 	}
 
 	pub fn split(self) -> (PathBuf, Vec<u8>) {
-		(self.path, self.file)
+		let buf = match self.file {
+			Payload::Owned(buf) => buf,
+			Payload::Shared(buf, range) => (*buf).as_ref()[range].to_vec(),
+		};
+		(self.path, buf)
 	}
 
 	pub fn path(&self) -> &Path {
@@ -55,11 +96,26 @@ impl File {
 	}
 
 	pub fn buf(&self) -> &[u8] {
-		self.file.as_slice()
+		match &self.file {
+			Payload::Owned(buf) => buf.as_slice(),
+			Payload::Shared(buf, range) => &(**buf).as_ref()[range.clone()],
+		}
 	}
 
+	/// Copies shared contents into a buffer owned by this file first
 	pub fn buf_mut(&mut self) -> &mut Vec<u8> {
-		&mut self.file
+		if let Payload::Shared(..) = self.file {
+			self.file = Payload::Owned(self.buf().to_vec());
+		}
+		match &mut self.file {
+			Payload::Owned(buf) => buf,
+			Payload::Shared(..) => unreachable!("converted above"),
+		}
+	}
+
+	/// Whether the contents point into a shared buffer, see [`File::from_shared`]
+	pub fn is_shared(&self) -> bool {
+		matches!(self.file, Payload::Shared(..))
 	}
 
 	pub fn as_ref(&self) -> (&Path, &[u8]) {
@@ -71,6 +71,7 @@
 use std::{
 	io::Write,
 	mem::size_of,
+	ops::Range,
 	path::{Path, PathBuf},
 };
 
@@ -86,11 +87,61 @@ use crate::{
 	util::join_hex,
 	vromf::{
 		File,
+		file::SharedBuf,
 		util::{bytes_to_int, bytes_to_usize},
 	},
 };
 
 pub fn decode_inner_vromf(file: &[u8], validate: bool) -> Result<Vec<File>, Report> {
+	Ok(decode_inner_vromf_with_digests(file, validate)?.0)
+}
+
+/// SHA1 of each file, in the same order as the files
+pub type Digests = Vec<[u8; 20]>;
+
+/// Same as [`decode_inner_vromf`], also returning the SHA1 digest of each file when the container stores them
+pub fn decode_inner_vromf_with_digests(
+	file: &[u8],
+	validate: bool,
+) -> Result<(Vec<File>, Option<Digests>), Report> {
+	let (entries, digests) = decode_entries(file, validate)?;
+	let files = entries
+		.into_iter()
+		.map(|(path, range)| File::from_raw(path, file[range].to_vec()))
+		.collect();
+	Ok((files, digests))
+}
+
+/// Same as [`decode_inner_vromf_with_digests`], with files pointing into `buf` instead of copying their payload
+///
+/// `inner` is the range of `buf` holding the inner container
+pub fn decode_inner_vromf_shared(
+	buf: &SharedBuf,
+	inner: Range<usize>,
+	validate: bool,
+) -> Result<(Vec<File>, Option<Digests>), Report> {
+	let file = (**buf)
+		.as_ref()
+		.get(inner.clone())
+		.context("Inner container exceeds the shared buffer")?;
+	let (entries, digests) = decode_entries(file, validate)?;
+	let files = entries
+		.into_iter()
+		.map(|(path, range)| {
+			File::from_shared(
+				path,
+				buf.clone(),
+				(inner.start + range.start)..(inner.start + range.end),
+			)
+		})
+		.collect();
+	Ok((files, digests))
+}
+
+// Path and payload range of each file
+type Entries = Vec<(PathBuf, Range<usize>)>;
+
+fn decode_entries(file: &[u8], validate: bool) -> Result<(Entries, Option<Digests>), Report> {
 	// Returns slice offset from file, incrementing the ptr by offset
 	let idx_file_offset = |ptr: &mut usize, offset: usize| {
 		if let Some(res) = file.get(*ptr..(*ptr + offset)) {
@@ -139,6 +190,12 @@ pub fn decode_inner_vromf(file: &[u8], validate: bool) -> Result<Vec<File>, Repo
 		None
 	};
 
+	let digests = digest_data.clone().filter(|_| has_digest).map(|chunks| {
+		chunks
+			.map(|e| e.try_into().expect("Infallible, chunks are 20 bytes"))
+			.collect()
+	});
+
 	// Names info is a set of u64s, pointing at each name
 	let names_info_len = names_count * size_of::<u64>();
 	let names_info = &file[names_offset..(names_offset + names_info_len)];
@@ -193,8 +250,14 @@ pub fn decode_inner_vromf(file: &[u8], validate: bool) -> Result<Vec<File>, Repo
 				u32::from_le_bytes(x[1]) as usize,
 			)
 		})
-		.map(|(offset, size)| file[offset..(offset + size)].to_vec())
-		.map(|e| {
+		.map(|(offset, size)| {
+			let range = offset..(offset + size);
+			file.get(range.clone()).with_context(|| {
+				format!(
+					"Data at {range:?} exceeds inner container of size {}",
+					file.len()
+				)
+			})?;
 			// Check digest only if the file should have one
 			if validate && has_digest {
 				let digest = digest_data
@@ -202,7 +265,7 @@ pub fn decode_inner_vromf(file: &[u8], validate: bool) -> Result<Vec<File>, Repo
 					.map(|e| e.next())
 					.context("Digest missing")?
 					.context("Too few digest elements")?;
-				let h = Sha1::from(&e).digest().bytes();
+				let h = Sha1::from(&file[range.clone()]).digest().bytes();
 				if digest != &h {
 					println!(
 						"Hash mismatch: expected: {} but found {}",
@@ -211,13 +274,11 @@ pub fn decode_inner_vromf(file: &[u8], validate: bool) -> Result<Vec<File>, Repo
 					);
 				}
 			}
-			Ok(e)
+			Ok(range)
 		});
 
-	Ok(convert(file_names)
-		.zip(convert(data))
-		.map(|(p, f)| Ok(File::from_raw(p, f)))
-		.collect()?)
+	let entries = convert(file_names).zip(convert(data)).collect()?;
+	Ok((entries, digests))
 }
 
 pub fn encode_inner_vromf(files: Vec<File>, digest_header: u8) -> Result<Vec<u8>, Report> {
@@ -15,6 +15,11 @@ mod util;
 /// This module unpacks the "outer" shell of the vromf image
 pub mod binary_container;
 
+mod builder;
+
+/// Comparison of two images, for example across game versions
+mod diff;
+
 pub(crate) mod file;
 pub mod header;
 pub mod inner_container;
@@ -22,7 +27,13 @@ pub mod inner_container;
 mod test;
 mod unpacker;
 
+/// Constant time lookup and listing of files inside an image
+mod vfs;
+
+pub use builder::VromfBuilder;
+pub use diff::{ChangedFile, VromfDiff, VromfDiffOptions};
 pub use enums::{HeaderType, Packing, PlatformType};
-pub use file::File;
+pub use file::{File, SharedBuf};
 pub use header::Metadata;
 pub use unpacker::{BlkOutputFormat, ContinueMode, FileFilter, VromfUnpacker, ZipFormat};
+pub use vfs::{DirEntry, FileMetadata, Vfs};
@@ -2,11 +2,17 @@ use std::{fs, path::PathBuf, str::FromStr};
 
 use wt_version::Version;
 
-use crate::vromf::{
-	File,
-	binary_container::decode_bin_vromf,
-	inner_container::decode_inner_vromf,
-	unpacker::{BlkOutputFormat, ContinueMode, FileFilter, VromfUnpacker, ZipFormat},
+use crate::{
+	blk::{blk_type::BlkType, diff::DiffOptions, include::resolve, name_map::NamesDigestCheck},
+	vromf::{
+		File,
+		Packing,
+		VromfBuilder,
+		VromfDiffOptions,
+		binary_container::decode_bin_vromf,
+		inner_container::decode_inner_vromf,
+		unpacker::{BlkOutputFormat, ContinueMode, FileFilter, VromfUnpacker, ZipFormat},
+	},
 };
 
 #[test]
@@ -87,6 +93,35 @@ fn no_nm_vromf() {
 	assert_eq!(8924, unpacked.len())
 }
 
+#[test]
+fn nm_digests() {
+	for path in [
+		"./samples/regional.vromfs.bin",
+		"./samples/grp_hdr.vromfs.bin",
+		"./samples/char.vromfs.bin",
+	] {
+		let out = VromfUnpacker::from_file(&File::new(path).unwrap(), true, false).unwrap();
+		out.validate_nm_digests().unwrap();
+	}
+}
+
+#[test]
+fn names_digest_schemes() {
+	let names_valid = |path: &str| {
+		let out = VromfUnpacker::from_file(&File::new(path).unwrap(), true, false).unwrap();
+		out.nm_digests().unwrap().validate_names(&out.nm().unwrap()) == NamesDigestCheck::Matches
+	};
+	// Only part of the game files use the SHA1 over the names, so a mismatch is not an error
+	assert!(names_valid(
+		"./samples/checked_simple_uncompressed_checked.vromfs.bin"
+	));
+	assert!(names_valid(
+		"./samples/unchecked_extended_compressed_checked.vromfs.bin"
+	));
+	assert!(!names_valid("./samples/char.vromfs.bin"));
+	assert!(!names_valid("./samples/regional.vromfs.bin"));
+}
+
 #[test]
 fn decode_simple() {
 	let f = fs::read("./samples/checked_simple_uncompressed_checked.vromfs.bin").unwrap();
@@ -94,6 +129,47 @@ fn decode_simple() {
 	let _ = decode_inner_vromf(&decoded, true).unwrap();
 }
 
+#[test]
+fn resolve_includes() {
+	let unpacker = VromfUnpacker::from_file(
+		&File::new("./samples/checked_simple_uncompressed_checked.vromfs.bin").unwrap(),
+		true,
+		false,
+	)
+	.unwrap();
+	let mut files = unpacker
+		.clone()
+		.unpack_all(None, false, FileFilter::All)
+		.unwrap();
+	files.push(File::from_raw(
+		PathBuf::from("mods/main.blk"),
+		b"include \"#config/section_slim_zst_dict.blk\"\n@override:int:i=7\n".to_vec(),
+	));
+	let packed = VromfBuilder::from_files(files)
+		.unwrap()
+		.with_metadata(unpacker.metadata().clone())
+		.build()
+		.unwrap();
+	let unpacker = VromfUnpacker::from_file(
+		&File::from_raw(PathBuf::from("packed.vromfs.bin"), packed),
+		true,
+		false,
+	)
+	.unwrap();
+
+	let base = resolve(&unpacker, "config/section_slim_zst_dict.blk").unwrap();
+	let resolved = resolve(&unpacker, "mods/main.blk").unwrap();
+	assert_eq!(
+		*resolved.pointer("int").unwrap().value().unwrap(),
+		BlkType::Int(7)
+	);
+	assert_eq!(
+		resolved.pointer("alpha/gamma/transform").unwrap(),
+		base.pointer("alpha/gamma/transform").unwrap()
+	);
+	assert!(resolve(&unpacker, "mods/missing.blk").is_err());
+}
+
 #[test]
 fn version() {
 	let out =
@@ -134,3 +210,112 @@ fn new_format() {
 // 		)))
 // 		.unwrap();
 // }
+
+#[test]
+fn diff_images() {
+	let pack = |files: &[(&str, &str)], version: &str, digest: bool| {
+		let packed = VromfBuilder::from_files(files.iter().map(|(path, content)| {
+			File::from_raw(PathBuf::from(path), content.as_bytes().to_vec())
+		}))
+		.unwrap()
+		.version(Version::from_str(version).unwrap())
+		.digest(digest)
+		.build()
+		.unwrap();
+		VromfUnpacker::from_file(
+			&File::from_raw(PathBuf::from("test.vromfs.bin"), packed),
+			true,
+			false,
+		)
+		.unwrap()
+	};
+	let old = pack(
+		&[
+			("config/unit.blk", "speed:r=1\nmass:r=5"),
+			("same.txt", "x"),
+			("gone.txt", "y"),
+		],
+		"2.25.0.1",
+		true,
+	);
+	let new = pack(
+		&[
+			("config/unit.blk", "speed:r=2\nmass:r=5"),
+			("same.txt", "x"),
+			("added.txt", "z"),
+		],
+		"2.27.0.1",
+		false,
+	);
+
+	let diff = old.diff(&new).unwrap();
+	assert_eq!(
+		diff.old_version,
+		Some(Version::from_str("2.25.0.1").unwrap())
+	);
+	assert_eq!(
+		diff.new_version,
+		Some(Version::from_str("2.27.0.1").unwrap())
+	);
+	assert_eq!(diff.added, [PathBuf::from("added.txt")]);
+	assert_eq!(diff.removed, [PathBuf::from("gone.txt")]);
+	assert_eq!(diff.changed.len(), 1);
+	assert_eq!(diff.changed[0].path, PathBuf::from("config/unit.blk"));
+	assert_eq!(diff.changed[0].blk_changes, None);
+
+	let diff = old
+		.diff_with(
+			&new,
+			VromfDiffOptions {
+				blk: Some(DiffOptions::default()),
+			},
+		)
+		.unwrap();
+	assert_eq!(
+		diff.to_string(),
+		"Version 2.25.0.1 -> 2.27.0.1\n+ added.txt\n- gone.txt\n~ config/unit.blk\n\t~ speed:r = 1 -> 2\n"
+	);
+	assert!(new.diff(&new).unwrap().changed.is_empty());
+}
+
+#[test]
+fn shared_and_mapped() {
+	let files = [
+		("config/unit.blk", "speed:r=1\nmass:r=5"),
+		("readme.txt", "plain text"),
+	];
+	let pack = |packing: Packing| {
+		VromfBuilder::from_files(files.iter().map(|(path, content)| {
+			File::from_raw(PathBuf::from(path), content.as_bytes().to_vec())
+		}))
+		.unwrap()
+		.packing(packing)
+		.version(Version::from_str("2.27.0.1").unwrap())
+		.build()
+		.unwrap()
+	};
+	let unpacked = |unpacker: VromfUnpacker| {
+		unpacker
+			.unpack_all(None, false, FileFilter::All)
+			.unwrap()
+			.into_iter()
+			.map(File::split)
+			.collect::<Vec<_>>()
+	};
+
+	for packing in [Packing::PLAIN, Packing::ZSTD_OBFS] {
+		let file = File::from_raw(PathBuf::from("test.vromfs.bin"), pack(packing));
+		let expected = unpacked(VromfUnpacker::from_file(&file, true, false).unwrap());
+
+		let shared = VromfUnpacker::from_file_shared(&file, true, false).unwrap();
+		assert!(shared.raw_files().iter().all(File::is_shared));
+		assert_eq!(unpacked(shared), expected);
+
+		let path = std::env::temp_dir().join(format!("wt_blk_mapped_{packing:?}.vromfs.bin"));
+		fs::write(&path, file.buf()).unwrap();
+		let mapped = VromfUnpacker::from_path_mapped(&path, true, false).unwrap();
+		assert!(mapped.raw_files().iter().all(File::is_shared));
+		assert_eq!(unpacked(mapped), expected);
+		fs::remove_file(path).unwrap();
+	}
+}
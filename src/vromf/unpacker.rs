@@ -1,9 +1,10 @@
 use std::{
 	ffi::OsStr,
 	fmt::{Debug, Formatter},
+	fs,
 	io::{Cursor, Write},
 	mem,
-	ops::Deref,
+	ops::{Deref, Range},
 	path::{Path, PathBuf},
 	str::FromStr,
 	sync::Arc,
@@ -14,20 +15,32 @@ use color_eyre::{
 	Report,
 	eyre::{Context, ContextCompat, eyre},
 };
+use memmap2::Mmap;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use regex::Regex;
+use sha1_smol::Sha1;
 use wt_version::Version;
 use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
 use zstd::dict::DecoderDictionary;
 
 use crate::{
 	blk,
-	blk::{blk_type::BlkFormatting, name_map::NameMap, util::maybe_blk},
+	blk::{
+		blk_structure::BlkField,
+		blk_type::BlkFormatting,
+		error::{NmDigestError, TextParseError},
+		include::BlkLoader,
+		name_map::{NameMap, NmDigests},
+		plaintext_deserialize::deserialize_blk,
+		util::maybe_blk,
+	},
 	vromf::{
 		File,
-		binary_container::decode_bin_vromf,
+		SharedBuf,
+		binary_container::{Inner, decode_bin_vromf, decode_bin_vromf_lazy},
 		header::Metadata,
-		inner_container::decode_inner_vromf,
+		inner_container::{Digests, decode_inner_vromf_shared, decode_inner_vromf_with_digests},
+		vfs::PathIndex,
 	},
 };
 
@@ -53,16 +66,24 @@ impl<'a> Deref for DictWrapper {
 /// Unpacks vromf image into all internal files, optionally formatting binary BLK files
 #[derive(Debug, Clone)]
 pub struct VromfUnpacker {
-	files:    Vec<File>,
-	dict:     Option<Arc<DictWrapper>>,
-	nm:       Option<Arc<NameMap>>,
-	metadata: Metadata,
+	files:       Vec<File>,
+	dict:        Option<Arc<DictWrapper>>,
+	// SHA-256 of the dictionary, taken from its filename when not validating
+	dict_digest: Option<[u8; 32]>,
+	nm:          Option<Arc<NameMap>>,
+	nm_digests:  Option<NmDigests>,
+	// SHA1 of each file as stored in the inner container, in the same order as `files`
+	digests:     Option<Digests>,
+	index:       Arc<PathIndex>,
+	metadata:    Metadata,
 }
 
 /// Defines plaintext format should be exported to
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum BlkOutputFormat {
 	Json,
+	/// Lossless JSON keeping types, order and duplicate keys, see [`blk::blk_structure::BlkField::as_typed_json_streaming`]
+	JsonTyped,
 	BlkText,
 	BlkCompact,
 }
@@ -75,6 +96,26 @@ pub enum ContinueMode {
 	Quiet,
 }
 
+// The dictionary is named after its SHA-256, the name is only trusted when not validating
+fn dict_digest_of(file: &File, validate: bool) -> [u8; 32] {
+	let from_name = || {
+		let stem = file
+			.path()
+			.file_stem()
+			.and_then(OsStr::to_str)
+			.filter(|stem| stem.len() == 64)?;
+		let mut digest = [0; 32];
+		for (i, byte) in digest.iter_mut().enumerate() {
+			*byte = u8::from_str_radix(stem.get(i * 2..i * 2 + 2)?, 16).ok()?;
+		}
+		Some(digest)
+	};
+	(!validate)
+		.then(from_name)
+		.flatten()
+		.unwrap_or_else(|| NameMap::dict_digest(file.buf()))
+}
+
 // Yields closure to filter failed/completed according to mode
 fn continue_filter<T>(mode: ContinueMode) -> impl for<'a> Fn(&'a Result<T, Report>) -> bool {
 	move |e: &Result<T, Report>| {
@@ -100,7 +141,9 @@ fn continue_filter<T>(mode: ContinueMode) -> impl for<'a> Fn(&'a Result<T, Repor
 impl BlkOutputFormat {
 	pub fn map_to_formatter(self) -> BlkFormatting {
 		match self {
-			BlkOutputFormat::Json | BlkOutputFormat::BlkText => BlkFormatting::standard(),
+			BlkOutputFormat::Json | BlkOutputFormat::JsonTyped | BlkOutputFormat::BlkText => {
+				BlkFormatting::standard()
+			},
 			BlkOutputFormat::BlkCompact => BlkFormatting::compact(),
 		}
 	}
@@ -162,15 +205,81 @@ impl VromfUnpacker {
 	// TODO: dump_parsed_nm should maybe be an argument passed to the other unpack functions, not the struct
 	pub fn from_file(file: &File, validate: bool, dump_parsed_nm: bool) -> Result<Self, Report> {
 		let (decoded, mut metadata) = decode_bin_vromf(file.buf(), validate)?;
-		metadata.digest = Some(decoded[0] == 0x30);
-		let mut inner = decode_inner_vromf(&decoded, validate)?;
+		metadata.digest = decoded.first().map(|&e| e == 0x30);
+		let (inner, digests) = decode_inner_vromf_with_digests(&decoded, validate)?;
+		Self::from_inner(inner, digests, metadata, validate, dump_parsed_nm)
+	}
+
+	/// Same as [`VromfUnpacker::from_file`], except that files point into one shared buffer instead of being copied individually
+	///
+	/// Files are only copied once they are unpacked into another format, raw files share the decoded image
+	pub fn from_file_shared(
+		file: &File,
+		validate: bool,
+		dump_parsed_nm: bool,
+	) -> Result<Self, Report> {
+		let (inner, metadata) = decode_bin_vromf_lazy(file.buf(), validate)?;
+		let buf: SharedBuf = match inner {
+			Inner::Range(range) => Arc::new(file.buf()[range].to_vec()),
+			Inner::Decoded(decoded) => Arc::new(decoded),
+		};
+		let len = (*buf).as_ref().len();
+		Self::from_shared(buf, 0..len, metadata, validate, dump_parsed_nm)
+	}
+
+	/// Memory-maps the image at `path`, files of uncompressed images point directly into the mapping
+	///
+	/// Compressed images are decoded into one buffer shared by all files, see [`VromfUnpacker::from_file_shared`].
+	/// The file must not be modified while the unpacker or any of its files are alive
+	pub fn from_path_mapped(
+		path: impl AsRef<Path>,
+		validate: bool,
+		dump_parsed_nm: bool,
+	) -> Result<Self, Report> {
+		let path = path.as_ref();
+		let file = fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+		// SAFETY: The image is treated as read-only, callers must not modify it while mapped
+		let map = unsafe { Mmap::map(&file)? };
+		let (inner, metadata) = decode_bin_vromf_lazy(&map, validate)?;
+		let (buf, range): (SharedBuf, _) = match inner {
+			Inner::Range(range) => (Arc::new(map), range),
+			Inner::Decoded(decoded) => {
+				let len = decoded.len();
+				(Arc::new(decoded), 0..len)
+			},
+		};
+		Self::from_shared(buf, range, metadata, validate, dump_parsed_nm)
+	}
+
+	fn from_shared(
+		buf: SharedBuf,
+		range: Range<usize>,
+		mut metadata: Metadata,
+		validate: bool,
+		dump_parsed_nm: bool,
+	) -> Result<Self, Report> {
+		metadata.digest = (*buf).as_ref().get(range.start).map(|&e| e == 0x30);
+		let (inner, digests) = decode_inner_vromf_shared(&buf, range, validate)?;
+		Self::from_inner(inner, digests, metadata, validate, dump_parsed_nm)
+	}
 
-		let nm = inner
+	fn from_inner(
+		mut inner: Vec<File>,
+		digests: Option<Digests>,
+		metadata: Metadata,
+		validate: bool,
+		dump_parsed_nm: bool,
+	) -> Result<Self, Report> {
+		let nm_file = inner
 			.iter()
-			.find(|elem| elem.path().file_name() == Some(OsStr::new("nm")))
+			.find(|elem| elem.path().file_name() == Some(OsStr::new("nm")));
+		let nm = nm_file
 			.map(|elem| NameMap::from_encoded_file(&elem.buf()))
 			.transpose()?
 			.map(|elem| Arc::new(elem));
+		let nm_digests = nm_file
+			.map(|elem| NmDigests::from_encoded_file(elem.buf()))
+			.transpose()?;
 
 		if let Some(nm) = nm.as_ref()
 			&& dump_parsed_nm
@@ -189,17 +298,41 @@ impl VromfUnpacker {
 			));
 		}
 
-		let dict = inner
+		let dict_file = inner
 			.iter()
-			.find(|elem| elem.path().extension() == Some(OsStr::new("dict")))
-			.map(|elem| Arc::new(DictWrapper(DecoderDictionary::copy(&elem.buf()))));
+			.find(|elem| elem.path().extension() == Some(OsStr::new("dict")));
+		let dict =
+			dict_file.map(|elem| Arc::new(DictWrapper(DecoderDictionary::copy(&elem.buf()))));
+		let dict_digest = dict_file.map(|file| dict_digest_of(file, validate));
 
-		Ok(Self {
+		let unpacker = Self {
+			index: Arc::new(PathIndex::new(&inner)),
 			files: inner,
 			dict,
+			dict_digest,
 			nm,
+			nm_digests,
+			digests,
 			metadata,
-		})
+		};
+		if validate {
+			unpacker.validate_nm_digests()?;
+		}
+		Ok(unpacker)
+	}
+
+	/// Checks the dict digest stored in the nm header against the dictionary that was picked, done on construction when validating
+	/// A mismatch usually means the nm and dict stem from different game versions
+	pub fn validate_nm_digests(&self) -> Result<(), NmDigestError> {
+		if let Some(digests) = self.nm_digests {
+			digests.validate_dict(self.dict_digest)?;
+		}
+		Ok(())
+	}
+
+	/// Digests stored in the header of the nm file, names can be checked using [`NmDigests::validate_names`]
+	pub fn nm_digests(&self) -> Option<NmDigests> {
+		self.nm_digests
 	}
 
 	pub fn unpack_all(
@@ -354,15 +487,14 @@ impl VromfUnpacker {
 		apply_overrides: bool,
 	) -> Result<File, Report> {
 		let file = self
-			.files
-			.iter()
-			.find(|e| e.path() == path_name)
+			.index
+			.position(path_name)
+			.map(|i| self.files[i].to_owned())
 			.context(format!(
 				"File {} was not found in VROMF",
 				path_name.to_string_lossy()
 			))
-			.suggestion("Validate file-name and ensure it was typed correctly")?
-			.to_owned();
+			.suggestion("Validate file-name and ensure it was typed correctly")?;
 		self.unpack_file(file, unpack_blk_into, apply_overrides)
 	}
 
@@ -408,6 +540,12 @@ impl VromfUnpacker {
 							}
 							parsed.as_serde_json_streaming(&mut writer)?;
 						},
+						BlkOutputFormat::JsonTyped => {
+							if apply_overrides {
+								parsed.apply_overrides(false);
+							}
+							parsed.as_typed_json_streaming(&mut writer)?;
+						},
 					}
 				} else {
 					// Default to the raw file
@@ -448,6 +586,24 @@ impl VromfUnpacker {
 		Ok(versions.last().map(|e| e.to_owned()))
 	}
 
+	/// Files as stored in the image, without any unpacking applied
+	pub(crate) fn raw_files(&self) -> &[File] {
+		&self.files
+	}
+
+	pub(crate) fn path_index(&self) -> &PathIndex {
+		&self.index
+	}
+
+	/// SHA1 of the file at `index` of [`VromfUnpacker::raw_files`], computed if the image stores none
+	pub(crate) fn file_digest(&self, index: usize) -> [u8; 20] {
+		self.digests
+			.as_ref()
+			.and_then(|e| e.get(index))
+			.copied()
+			.unwrap_or_else(|| Sha1::from(self.files[index].buf()).digest().bytes())
+	}
+
 	pub fn list_files(&self) {
 		for f in &self.files {
 			println!("{}", f.path().to_string_lossy());
@@ -466,3 +622,19 @@ impl VromfUnpacker {
 		&self.metadata
 	}
 }
+
+/// Loads binary BLK as well as BLK text, which allows resolving includes inside the image
+impl BlkLoader for VromfUnpacker {
+	fn load(&self, path: &str) -> Result<BlkField, Report> {
+		let mut file = self
+			.index
+			.position(Path::new(path))
+			.map(|i| self.files[i].clone())
+			.context(format!("File {path} was not found in VROMF"))?;
+		if maybe_blk(&file) {
+			blk::unpack_blk(file.buf_mut(), self.dict(), self.nm.clone())
+		} else {
+			deserialize_blk(str::from_utf8(file.buf())?).map_err(TextParseError::into_report)
+		}
+	}
+}
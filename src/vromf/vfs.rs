@@ -0,0 +1,226 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	path::{Path, PathBuf},
+};
+
+use color_eyre::{Report, eyre::ContextCompat};
+
+use crate::vromf::{File, VromfUnpacker};
+
+/// Read-only view of an image as a filesystem, see [`VromfUnpacker::vfs`]
+///
+/// Paths are relative to the root of the image, a leading `/` is ignored and the root itself is the empty path.
+/// Lookups are constant time, as the index is built once when the image is opened
+#[derive(Debug, Clone, Copy)]
+pub struct Vfs<'a> {
+	unpacker: &'a VromfUnpacker,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DirEntry {
+	/// Full path of the entry, not just its name
+	pub path:   PathBuf,
+	pub is_dir: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileMetadata {
+	/// Size in bytes as stored in the image, before unpacking
+	pub size: usize,
+	/// Taken from the image when it stores digests, computed otherwise
+	pub sha1: [u8; 20],
+}
+
+/// Position of each file and entries of each directory
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PathIndex {
+	files: HashMap<PathBuf, usize>,
+	dirs:  HashMap<PathBuf, Vec<DirEntry>>,
+}
+
+impl PathIndex {
+	pub(crate) fn new(files: &[File]) -> Self {
+		let mut dirs: HashMap<PathBuf, BTreeMap<PathBuf, bool>> = HashMap::new();
+		dirs.insert(PathBuf::new(), BTreeMap::new());
+		for file in files {
+			let mut child = key(file.path());
+			let mut is_dir = false;
+			while let Some(parent) = child.parent() {
+				let entries = dirs.entry(parent.to_path_buf()).or_default();
+				let known = entries.contains_key(child);
+				entries.insert(child.to_path_buf(), is_dir);
+				// Ancestors were registered by an earlier file
+				if known && is_dir {
+					break;
+				}
+				child = parent;
+				is_dir = true;
+			}
+		}
+
+		Self {
+			// The first file wins for duplicate paths, same as a linear search would
+			files: files
+				.iter()
+				.enumerate()
+				.rev()
+				.map(|(i, file)| (key(file.path()).to_path_buf(), i))
+				.collect(),
+			dirs:  dirs
+				.into_iter()
+				.map(|(dir, entries)| {
+					let entries = entries
+						.into_iter()
+						.map(|(path, is_dir)| DirEntry { path, is_dir })
+						.collect();
+					(dir, entries)
+				})
+				.collect(),
+		}
+	}
+
+	/// Position of the file in [`VromfUnpacker::raw_files`]
+	pub(crate) fn position(&self, path: &Path) -> Option<usize> {
+		self.files.get(key(path)).copied()
+	}
+}
+
+// Paths are stored without leading slash
+fn key(path: &Path) -> &Path {
+	path.strip_prefix("/").unwrap_or(path)
+}
+
+impl VromfUnpacker {
+	/// Filesystem view for looking up and listing individual files
+	pub fn vfs(&self) -> Vfs<'_> {
+		Vfs { unpacker: self }
+	}
+}
+
+impl<'a> Vfs<'a> {
+	/// True for files as well as directories
+	pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+		self.is_file(&path) || self.is_dir(&path)
+	}
+
+	pub fn is_file(&self, path: impl AsRef<Path>) -> bool {
+		self.unpacker.path_index().position(path.as_ref()).is_some()
+	}
+
+	pub fn is_dir(&self, path: impl AsRef<Path>) -> bool {
+		self.unpacker
+			.path_index()
+			.dirs
+			.contains_key(key(path.as_ref()))
+	}
+
+	/// Entries directly inside the directory, sorted by path
+	pub fn read_dir(&self, path: impl AsRef<Path>) -> Result<&'a [DirEntry], Report> {
+		let path = path.as_ref();
+		self.unpacker
+			.path_index()
+			.dirs
+			.get(key(path))
+			.map(Vec::as_slice)
+			.with_context(|| format!("Directory {} was not found in VROMF", path.display()))
+	}
+
+	pub fn metadata(&self, path: impl AsRef<Path>) -> Result<FileMetadata, Report> {
+		let index = self.position(path.as_ref())?;
+		Ok(FileMetadata {
+			size: self.unpacker.raw_files()[index].buf().len(),
+			sha1: self.unpacker.file_digest(index),
+		})
+	}
+
+	/// Contents as stored in the image, without copying them, use [`VromfUnpacker::unpack_one`] for unpacked BLK
+	pub fn read(&self, path: impl AsRef<Path>) -> Result<&'a [u8], Report> {
+		let index = self.position(path.as_ref())?;
+		Ok(self.unpacker.raw_files()[index].buf())
+	}
+
+	fn position(&self, path: &Path) -> Result<usize, Report> {
+		self.unpacker
+			.path_index()
+			.position(path)
+			.with_context(|| format!("File {} was not found in VROMF", path.display()))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::{Path, PathBuf};
+
+	use sha1_smol::Sha1;
+
+	use crate::vromf::{File, VromfBuilder, VromfUnpacker, vfs::DirEntry};
+
+	#[test]
+	fn lookup_and_listing() {
+		let files = [
+			"gameData/units/tankModels/a.blk",
+			"gameData/units/tankModels/b.blk",
+			"gameData/units/planes/c.blk",
+			"readme.txt",
+		];
+		let packed = VromfBuilder::from_files(
+			files
+				.iter()
+				.map(|path| File::from_raw(PathBuf::from(path), path.as_bytes().to_vec())),
+		)
+		.unwrap()
+		.version("2.27.0.1".parse().unwrap())
+		.build()
+		.unwrap();
+		let unpacker = VromfUnpacker::from_file(
+			&File::from_raw(PathBuf::from("test.vromfs.bin"), packed),
+			true,
+			false,
+		)
+		.unwrap();
+		let vfs = unpacker.vfs();
+
+		let entry = |path: &str, is_dir| DirEntry {
+			path: PathBuf::from(path),
+			is_dir,
+		};
+		assert_eq!(
+			vfs.read_dir("").unwrap(),
+			[entry("gameData", true), entry("readme.txt", false)]
+		);
+		assert_eq!(
+			vfs.read_dir("/gameData/units").unwrap(),
+			[
+				entry("gameData/units/planes", true),
+				entry("gameData/units/tankModels", true)
+			]
+		);
+		assert_eq!(
+			vfs.read_dir("gameData/units/tankModels").unwrap(),
+			[
+				entry("gameData/units/tankModels/a.blk", false),
+				entry("gameData/units/tankModels/b.blk", false)
+			]
+		);
+		assert!(vfs.read_dir("readme.txt").is_err());
+		assert!(vfs.read_dir("missing").is_err());
+
+		assert!(vfs.exists("gameData/units"));
+		assert!(vfs.exists("/readme.txt"));
+		assert!(!vfs.exists("gameData/unit"));
+		assert!(vfs.is_file("gameData/units/planes/c.blk"));
+		assert!(!vfs.is_file("gameData/units/planes"));
+
+		let path = "gameData/units/planes/c.blk";
+		let metadata = vfs.metadata(path).unwrap();
+		assert_eq!(metadata.size, path.len());
+		assert_eq!(metadata.sha1, Sha1::from(path).digest().bytes());
+		assert_eq!(vfs.read(path).unwrap(), path.as_bytes());
+		assert!(vfs.metadata("gameData").is_err());
+
+		let unpacked = unpacker
+			.unpack_one(Path::new("readme.txt"), None, false)
+			.unwrap();
+		assert_eq!(unpacked.buf(), b"readme.txt");
+	}
+}